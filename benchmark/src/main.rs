@@ -13,7 +13,12 @@ fn main() {
 
     let start_time = SystemTime::now();
 
-    let complex = rust_phi::system::search_complex(full_state, &full_state_tpm, NUM_THREADS, true);
+    let config = rust_phi::config::AnalysisConfig::builder()
+        .num_threads(NUM_THREADS)
+        .log(true)
+        .build()
+        .unwrap();
+    let complex = rust_phi::system::search_complex(full_state, &full_state_tpm, &config);
 
     println!("\nComplex: {:?}", complex.elements);
     println!("Total elapsed time: {:.2e}", start_time.elapsed().unwrap().as_secs_f64());
@@ -1,15 +1,25 @@
-use crate::bitwise::{USIZE_BASIS, generate_vectors_from_mask};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::bitwise::{BitSet, USIZE_BASIS, USIZE_BITS, generate_mask, generate_vectors_from_mask};
 
 
-pub struct CombinationIterator<'a> {
-    initial: usize,
+fn generate_unused_mask(dim: usize) -> usize {
+    if dim >= USIZE_BITS {
+        0 // too large to be spanned
+    } else {
+        usize::MAX << dim
+    }
+}
+
+pub struct CombinationIterator<'a, S: BitSet> {
+    initial: S,
     current: usize,
     index: Vec<usize>,
-    parent: &'a BitBasis,
+    parent: &'a BitBasis<S>,
 }
 
-impl <'a> Iterator for CombinationIterator<'a> {
-    type Item = usize;
+impl <'a, S: BitSet> Iterator for CombinationIterator<'a, S> {
+    type Item = S;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.current & self.parent.unused_mask != 0 {
@@ -27,11 +37,11 @@ impl <'a> Iterator for CombinationIterator<'a> {
         self.current += 1;
 
 
-        let mut union = self.initial;
+        let mut union = self.initial.clone();
 
-        self.index.iter().zip(&self.parent.vectors).for_each(|(&i, &b)|{
+        self.index.iter().zip(&self.parent.vectors).for_each(|(&i, b)|{
             if i != 0 {
-                union |= b;
+                union.union_with(b);
             }
         });
 
@@ -40,50 +50,55 @@ impl <'a> Iterator for CombinationIterator<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub struct BitBasis {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BitBasis<S: BitSet = usize> {
     pub dim: usize,
     pub codim: usize,
     pub max_dim: usize,
     pub unused_mask: usize,
-    pub vectors: Vec<usize>,
+    pub vectors: Vec<S>,
 }
 
-impl BitBasis {
-    pub fn construct_from_vectors(vectors: &[usize], max_dim: usize) -> BitBasis {
+impl <S: BitSet> BitBasis<S> {
+    pub fn construct_from_vectors(vectors: &[S], max_dim: usize) -> BitBasis<S> {
         let basis_dim = vectors.len();
-        let mut cloned_vectors = Vec::<usize>::with_capacity(basis_dim);
-        vectors.iter().for_each(|&x| cloned_vectors.push(x));
 
         BitBasis {
             dim: basis_dim,
             codim: max_dim - basis_dim,
-            max_dim: max_dim,
-            unused_mask: usize::MAX << basis_dim,
-            vectors: cloned_vectors,
+            max_dim,
+            unused_mask: generate_unused_mask(basis_dim),
+            vectors: vectors.to_vec(),
         }
     }
 
-    pub fn construct_from_mask(mask: usize, max_dim: usize) -> BitBasis {
-        BitBasis::construct_from_vectors(&generate_vectors_from_mask(mask), max_dim)
+    pub fn construct_from_mask(mask: S, max_dim: usize) -> BitBasis<S> {
+        BitBasis::construct_from_vectors(&generate_vectors_from_mask(&mask), max_dim)
     }
 
-    pub fn construct_from_max_image_size(max_image_size: usize) -> BitBasis {
+    pub fn construct_from_max_image_size(max_image_size: usize) -> BitBasis<S> {
         assert!(max_image_size != 0);
         assert!(max_image_size.count_ones() == 1);
 
-        let mask = max_image_size - 1;
-        let dim = mask.count_ones() as usize;
+        let dim = max_image_size.trailing_zeros() as usize;
+        let indices: Vec<usize> = (0..dim).collect();
+
+        BitBasis::construct_from_mask(generate_mask(&indices), dim)
+    }
+
+    pub fn construct_from_max_dim(max_dim: usize) -> BitBasis<S> {
+        let indices: Vec<usize> = (0..max_dim).collect();
 
-        BitBasis::construct_from_mask(mask, dim)
+        BitBasis::construct_from_mask(generate_mask(&indices), max_dim)
     }
 
-    pub fn generate_complement_basis(&self) -> BitBasis {
-        let union = self.vectors.iter().fold(0, |acc, &vector| acc | vector );
-        let mut complement = Vec::<usize>::with_capacity(self.codim);
+    pub fn generate_complement_basis(&self) -> BitBasis<S> {
+        let union = self.to_mask();
+        let mut complement = Vec::<S>::with_capacity(self.codim);
 
-        USIZE_BASIS[0..self.max_dim].iter().for_each(|&vector| {
-            if union & vector == 0 {
-                complement.push(vector);
+        (0..self.max_dim).for_each(|i| {
+            if !union.contains(i) {
+                complement.push(S::singleton(i));
             }
         });
 
@@ -91,13 +106,16 @@ impl BitBasis {
             dim: self.codim,
             codim: self.dim,
             max_dim: self.max_dim,
-            unused_mask: usize::MAX << self.codim,
+            unused_mask: generate_unused_mask(self.codim),
             vectors: complement,
         }
     }
 
-    pub fn to_mask(&self) -> usize {
-        self.vectors.iter().fold(0, |acc, &x| acc | x)
+    pub fn to_mask(&self) -> S {
+        self.vectors.iter().fold(S::empty(), |mut acc, x| {
+            acc.union_with(x);
+            acc
+        })
     }
 
     pub fn image_size(&self) -> usize {
@@ -112,45 +130,75 @@ impl BitBasis {
         1 << self.max_dim
     }
 
-    pub fn fixed_state(&self, state: usize) -> usize {
-        self.vectors.iter().fold(0, |acc, &vector| {
-            acc | (state & vector)
+    pub fn fixed_state(&self, state: &S) -> S {
+        self.vectors.iter().fold(S::empty(), |mut acc, vector| {
+            if state.intersects(vector) {
+                acc.union_with(vector);
+            }
+
+            acc
         })
     }
 
-    pub fn span(&self, initial: usize) -> CombinationIterator {
+    // return the state restricted to this basis as a compact index
+    pub fn compress(&self, state: &S) -> usize {
+        assert!(self.dim <= USIZE_BITS);
+
+        self.vectors.iter().enumerate().fold(0, |acc, (i, vector)| {
+            if state.intersects(vector) {
+                acc | USIZE_BASIS[i]
+            } else {
+                acc
+            }
+        })
+    }
+
+    // inverse of `compress`, the bits out of this basis are taken from `initial`
+    pub fn expand(&self, index: usize, initial: S) -> S {
+        self.vectors.iter().enumerate().fold(initial, |mut acc, (i, vector)| {
+            if index & USIZE_BASIS[i] != 0 {
+                acc.union_with(vector);
+            }
+
+            acc
+        })
+    }
+
+    pub fn span(&self, initial: S) -> CombinationIterator<'_, S> {
+        assert!(self.dim < USIZE_BITS, "Basis of {} dimensions cannot be spanned", self.dim);
+
         CombinationIterator {
-            initial: initial,
+            initial,
             current: 0,
             index: vec![0; self.dim],
             parent: self,
         }
     }
 
-    pub fn null_basis(max_dim: usize) -> BitBasis {
+    pub fn null_basis(max_dim: usize) -> BitBasis<S> {
         BitBasis {
             dim: 0,
             codim: max_dim,
-            max_dim: max_dim,
+            max_dim,
             unused_mask: usize::MAX,
             vectors: vec![],
         }
     }
 
-    pub fn sub_basis(&self, index: &[usize]) -> BitBasis {
+    pub fn sub_basis(&self, index: &[usize]) -> BitBasis<S> {
         let dim = index.len();
 
-        let mut vectors = Vec::<usize>::with_capacity(dim);
+        let mut vectors = Vec::<S>::with_capacity(dim);
         index.iter().for_each(|&i| {
-            vectors.push(self.vectors[i]);
+            vectors.push(self.vectors[i].clone());
         });
 
         BitBasis {
-            dim: dim,
+            dim,
             codim: self.max_dim - dim,
             max_dim: self.max_dim,
-            unused_mask: usize::MAX << dim,
-            vectors: vectors,
+            unused_mask: generate_unused_mask(dim),
+            vectors,
         }
     }
 }
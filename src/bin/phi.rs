@@ -0,0 +1,338 @@
+use std::{env, process, sync::Arc};
+use nalgebra as na;
+use rust_phi::{basis::BitBasis, config::AnalysisConfig, emd::DistanceMeasure, error::PhiError, mechanism::{RepertoireType, generate_all_repertoire_parts, try_search_concept_with_parts}, network::{Network, format_elements, format_state}, partition::{MechanismPartitionScheme, SystemPartitionScheme}, progress::{CancellationToken, Progress, ProgressObserver, SilentObserver}, system::{Complex, try_search_complex, try_search_constellation_with_mip}, tpm::validate, tpm_file::TpmFormat};
+
+
+const USAGE: &str = "\
+Usage: phi <COMMAND> <NETWORK> [OPTIONS]
+
+Commands:
+    tpm        print the state-by-state TPM of the network
+    concept    search the concept of a mechanism (needs --state and --mechanism)
+    mip        search the MIP of the whole network (needs --state)
+    complex    search the complex among all subsets of the network (needs --state)
+    sweep      search the complex in every state of the network
+
+NETWORK is a SIF file (*.sif) or a TPM file in CSV or plain text.
+
+Options:
+    --state <STATE>              current state such as `A=1,B=0,C=0` or `100` in element order
+    --mechanism <ELEMENTS>       elements of the mechanism such as `A,B`
+    --threads <N>                number of threads [default: 1]
+    --format <text|json>         output format [default: text]
+    --tpm-format <sbs|sbn>       layout of a TPM file, state-by-state or state-by-node [default: sbs]
+    --system-scheme <uni|bi|directed>    system partition scheme [default: uni]
+    --mechanism-scheme <bi|all|wedge>    mechanism partition scheme [default: bi]
+    --distance <emd|l1>          distance between repertoires [default: emd]
+    --tolerance <EPS>            tolerance of phi comparison [default: 1e-7]
+    --ties                       report all tied MIPs and core purviews
+    --log                        print progress of complex search to stderr
+    -h, --help                   print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Tpm,
+    Concept,
+    Mip,
+    Complex,
+    Sweep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+struct Options {
+    command: Command,
+    network: String,
+    state: Option<String>,
+    mechanism: Option<String>,
+    format: OutputFormat,
+    tpm_format: TpmFormat,
+    config: AnalysisConfig,
+}
+
+fn parse_command(text: &str) -> Result<Command, String> {
+    match text {
+        "tpm" => Ok(Command::Tpm),
+        "concept" => Ok(Command::Concept),
+        "mip" => Ok(Command::Mip),
+        "complex" => Ok(Command::Complex),
+        "sweep" => Ok(Command::Sweep),
+        _ => Err(format!("Unknown command '{}'", text)),
+    }
+}
+
+fn parse_choice<T: Copy>(option: &str, text: &str, choices: &[(&str, T)]) -> Result<T, String> {
+    choices.iter().find(|(name, _)| *name == text).map(|(_, value)| *value).ok_or_else(|| {
+        let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+        format!("{} must be one of {}, but '{}' is given", option, names.join("|"), text)
+    })
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut positionals = Vec::<&String>::new();
+    let mut state = None;
+    let mut mechanism = None;
+    let mut format = OutputFormat::Text;
+    let mut tpm_format = TpmFormat::StateByState;
+    let mut builder = AnalysisConfig::builder();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with('-') {
+            positionals.push(arg);
+            continue;
+        }
+
+        if arg == "--ties" {
+            builder = builder.report_ties(true);
+            continue;
+        }
+
+        if arg == "--log" {
+            builder = builder.log(true);
+            continue;
+        }
+
+        let value = iter.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--state" => state = Some(value.clone()),
+            "--mechanism" => mechanism = Some(value.clone()),
+            "--threads" => {
+                let num_threads = value.parse::<usize>().map_err(|_| format!("Invalid number of threads '{}'", value))?;
+                builder = builder.num_threads(num_threads);
+            },
+            "--format" => format = parse_choice(arg, value, &[("text", OutputFormat::Text), ("json", OutputFormat::Json)])?,
+            "--tpm-format" => {
+                tpm_format = parse_choice(arg, value, &[("sbs", TpmFormat::StateByState), ("sbn", TpmFormat::StateByNode)])?;
+            },
+            "--system-scheme" => {
+                let choices = [
+                    ("uni", SystemPartitionScheme::Unidirectional),
+                    ("bi", SystemPartitionScheme::Bidirectional),
+                    ("directed", SystemPartitionScheme::Directed),
+                ];
+                builder = builder.system_scheme(parse_choice(arg, value, &choices)?);
+            },
+            "--mechanism-scheme" => {
+                let choices = [
+                    ("bi", MechanismPartitionScheme::Bipartition),
+                    ("all", MechanismPartitionScheme::All),
+                    ("wedge", MechanismPartitionScheme::Wedge),
+                ];
+                builder = builder.mechanism_scheme(parse_choice(arg, value, &choices)?);
+            },
+            "--distance" => {
+                builder = builder.distance(parse_choice(arg, value, &[("emd", DistanceMeasure::Emd), ("l1", DistanceMeasure::L1)])?);
+            },
+            "--tolerance" => {
+                let tolerance = value.parse::<f64>().map_err(|_| format!("Invalid tolerance '{}'", value))?;
+                builder = builder.tolerance(tolerance);
+            },
+            _ => return Err(format!("Unknown option '{}'", arg)),
+        }
+    }
+
+    let (command, network) = match positionals[..] {
+        [command, network] => (parse_command(command)?, network.clone()),
+        _ => return Err("Both of a command and a network are needed".to_string()),
+    };
+
+    Ok(Options {
+        command,
+        network,
+        state,
+        mechanism,
+        format,
+        tpm_format,
+        config: builder.build().map_err(|e| e.to_string())?,
+    })
+}
+
+fn check_tpm(tpm: &na::DMatrix<f64>) -> Result<(), String> {
+    // the current state is checked by `parse_state`, only for a warning
+    let report = validate(tpm, 0);
+
+    let message = if !report.valid_shape {
+        format!("{}x{} is not a state-by-state TPM", tpm.nrows(), tpm.ncols())
+    } else if let Some((row, sum)) = report.unnormalized_rows.first() {
+        format!("Row {} sums to {}, not 1", row, sum)
+    } else if let Some((row, col)) = report.negative_entries.first() {
+        format!("Entry ({}, {}) is negative", row, col)
+    } else if let Some(row) = report.dependent_rows.first() {
+        format!("Row {} is not conditionally independent across elements", row)
+    } else {
+        return Ok(());
+    };
+
+    Err(PhiError::InvalidTpm(message).to_string())
+}
+
+fn load_network(options: &Options) -> Result<Network, String> {
+    if options.network.ends_with(".sif") {
+        return Network::read_sif(&options.network, options.config.num_threads).map_err(|e| e.to_string());
+    }
+
+    let network = Network::read_tpm(&options.network, options.tpm_format).map_err(|e| e.to_string())?;
+    check_tpm(&network.tpm)?;
+
+    Ok(network)
+}
+
+fn find_element(name: &str, network: &Network) -> Result<usize, String> {
+    network.element_index(name).map_err(|e| e.to_string())
+}
+
+fn split_list(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty())
+}
+
+fn parse_mechanism(text: &str, network: &Network) -> Result<Vec<usize>, String> {
+    let mut indices = Vec::<usize>::new();
+
+    for name in split_list(text) {
+        let index = find_element(name, network)?;
+
+        if indices.contains(&index) {
+            return Err(format!("Element '{}' is given twice in the mechanism", name));
+        }
+
+        indices.push(index);
+    }
+
+    if indices.is_empty() {
+        return Err("Mechanism has no element".to_string());
+    }
+
+    Ok(indices)
+}
+
+fn parse_state(text: &str, network: &Network) -> Result<usize, String> {
+    let state = network.parse_state(text).map_err(|e| e.to_string())?;
+
+    if !validate(&network.tpm, state).reachable {
+        eprintln!("warning: {} cannot be reached from any state", network.format_state(state));
+    }
+
+    Ok(state)
+}
+
+#[cfg(feature = "serde")]
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), String> {
+    let json = rust_phi::serialize::to_json(value).map_err(|e| e.to_string())?;
+    println!("{}", json);
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json<T>(_value: &T) -> Result<(), String> {
+    Err("JSON output needs `serde` feature".to_string())
+}
+
+// keeps stdout for results, such as JSON
+struct StderrObserver;
+
+impl ProgressObserver for StderrObserver {
+    fn notify(&self, progress: &Progress) {
+        eprintln!("{}", progress);
+    }
+}
+
+fn search_complex(state: usize, network: &Network, config: &AnalysisConfig) -> Result<Complex, String> {
+    let observer: &dyn ProgressObserver = if config.log { &StderrObserver } else { &SilentObserver };
+    try_search_complex(state, &network.tpm, config, observer, &CancellationToken::new()).map_err(|e| e.to_string())
+}
+
+fn require<'a>(value: &'a Option<String>, option: &str) -> Result<&'a str, String> {
+    value.as_deref().ok_or_else(|| format!("{} is needed", option))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let network = load_network(options)?;
+    let (names, tpm) = (&network.names, &network.tpm);
+    let config = &options.config;
+    let is_json = options.format == OutputFormat::Json;
+
+    match options.command {
+        Command::Tpm => {
+            if is_json {
+                print_json(&network.named(tpm))?;
+            } else {
+                println!("Elements: {}", names.join(", "));
+                println!("{}", tpm);
+            }
+        },
+        Command::Concept => {
+            let state = parse_state(require(&options.state, "--state")?, &network)?;
+            let indices = parse_mechanism(require(&options.mechanism, "--mechanism")?, &network)?;
+
+            let system_basis: BitBasis = BitBasis::construct_from_max_image_size(tpm.ncols());
+            let mechanism = system_basis.sub_basis(&indices);
+
+            let cause_parts = generate_all_repertoire_parts(RepertoireType::CAUSE, state, tpm);
+            let effect_parts = generate_all_repertoire_parts(RepertoireType::EFFECT, state, tpm);
+            let concept = try_search_concept_with_parts(&mechanism, &cause_parts, &effect_parts, config).map_err(|e| e.to_string())?;
+
+            if is_json {
+                print_json(&network.named(&concept))?;
+            } else {
+                println!("{:#}", network.named(&concept));
+            }
+        },
+        Command::Mip => {
+            let state = parse_state(require(&options.state, "--state")?, &network)?;
+            let constellation = try_search_constellation_with_mip(state, &Arc::new(tpm.clone()), config, &CancellationToken::new()).map_err(|e| e.to_string())?;
+
+            if is_json {
+                print_json(&network.named(&constellation))?;
+            } else {
+                println!("{:#}", network.named(&constellation));
+            }
+        },
+        Command::Complex => {
+            let state = parse_state(require(&options.state, "--state")?, &network)?;
+            let complex = search_complex(state, &network, config)?;
+
+            if is_json {
+                print_json(&network.named(&complex))?;
+            } else {
+                println!("{:#}", network.named(&complex));
+            }
+        },
+        Command::Sweep => {
+            let complexes = (0..tpm.nrows()).map(|state| {
+                Ok((format_state(state, names), search_complex(state, &network, config)?))
+            }).collect::<Result<Vec<(String, Complex)>, String>>()?;
+
+            if is_json {
+                print_json(&network.named(&complexes))?;
+            } else {
+                complexes.iter().for_each(|(state, complex)| {
+                    println!("{}: Complex: {}, Big phi: {}", state, format_elements(&complex.elements, names), complex.constellation.mip.phi);
+                });
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let result = parse_options(&args).and_then(|options| run(&options));
+
+    if let Err(message) = result {
+        eprintln!("error: {}", message);
+        eprintln!("Run `phi --help` for usage.");
+        process::exit(1);
+    }
+}
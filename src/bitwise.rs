@@ -1,7 +1,9 @@
-use std::{mem::size_of, usize};
+use std::{fmt::Debug, hash::Hash};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 
-pub const USIZE_BITS: usize = size_of::<usize>() * 8;
+pub const USIZE_BITS: usize = usize::BITS as usize;
 pub const USIZE_BASIS: [usize; 64] = [
     1 << 0, 1 << 1, 1 << 2, 1 << 3, 1 << 4, 1 << 5, 1 << 6, 1 << 7, 1 << 8, 1 << 9,
     1 << 10, 1 << 11, 1 << 12, 1 << 13, 1 << 14, 1 << 15, 1 << 16, 1 << 17, 1 << 18, 1 << 19,
@@ -11,36 +13,172 @@ pub const USIZE_BASIS: [usize; 64] = [
     1 << 50, 1 << 51, 1 << 52, 1 << 53, 1 << 54, 1 << 55, 1 << 56, 1 << 57, 1 << 58, 1 << 59,
     1 << 60, 1 << 61, 1 << 62, 1 << 63];
 
-pub fn generate_mask(indices: &Vec::<usize>) -> usize {
-    let mut mask: usize = 0;
+pub trait BitSet: Clone + Debug + PartialEq + Eq + Hash + Send + Sync {
+    fn empty() -> Self;
 
-    for index in indices {
-        mask |= 1 << index;
+    // return None if any index can be stored
+    fn capacity() -> Option<usize>;
+
+    fn contains(&self, index: usize) -> bool;
+
+    fn insert(&mut self, index: usize);
+
+    fn count_ones(&self) -> usize;
+
+    // return the index of the highest set bit plus one
+    fn bit_len(&self) -> usize;
+
+    fn union_with(&mut self, other: &Self);
+
+    fn intersects(&self, other: &Self) -> bool;
+
+    // add one as if the set were an unsigned integer
+    fn increment(&mut self);
+
+    fn singleton(index: usize) -> Self {
+        let mut set = Self::empty();
+        set.insert(index);
+        set
     }
 
-    mask
+    fn is_empty(&self) -> bool {
+        self.bit_len() == 0
+    }
 }
 
-pub fn generate_indices(mask: usize) -> Vec<usize> {
-    let mut indices = Vec::<usize>::new();
-    let mut digit = 1;
+macro_rules! impl_primitive_bit_set {
+    ($t:ty) => {
+        impl BitSet for $t {
+            fn empty() -> Self {
+                0
+            }
+
+            fn capacity() -> Option<usize> {
+                Some(<$t>::BITS as usize)
+            }
+
+            fn contains(&self, index: usize) -> bool {
+                index < <$t>::BITS as usize && *self & (1 << index) != 0
+            }
+
+            fn insert(&mut self, index: usize) {
+                assert!(index < <$t>::BITS as usize, "Index {} exceeds the capacity of {}", index, stringify!($t));
+                *self |= 1 << index;
+            }
+
+            fn count_ones(&self) -> usize {
+                <$t>::count_ones(*self) as usize
+            }
+
+            fn bit_len(&self) -> usize {
+                (<$t>::BITS - self.leading_zeros()) as usize
+            }
+
+            fn union_with(&mut self, other: &Self) {
+                *self |= *other;
+            }
+
+            fn intersects(&self, other: &Self) -> bool {
+                *self & *other != 0
+            }
+
+            fn increment(&mut self) {
+                *self += 1;
+            }
+        }
+    };
+}
+
+impl_primitive_bit_set!(usize);
+impl_primitive_bit_set!(u128);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HeapBitSet {
+    blocks: Vec<u64>, // no trailing zero blocks so that derived traits work
+}
+
+impl BitSet for HeapBitSet {
+    fn empty() -> Self {
+        HeapBitSet { blocks: Vec::new() }
+    }
+
+    fn capacity() -> Option<usize> {
+        None
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        match self.blocks.get(index / 64) {
+            Some(block) => block & (1 << (index % 64)) != 0,
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, index: usize) {
+        let block = index / 64;
+        if self.blocks.len() <= block {
+            self.blocks.resize(block + 1, 0);
+        }
+
+        self.blocks[block] |= 1 << (index % 64);
+    }
+
+    fn count_ones(&self) -> usize {
+        self.blocks.iter().map(|block| block.count_ones() as usize).sum()
+    }
 
-    (0..USIZE_BITS).for_each(|i| {
-        if mask & digit != 0 {
-            indices.push(i);
+    fn bit_len(&self) -> usize {
+        match self.blocks.last() {
+            Some(block) => self.blocks.len() * 64 - block.leading_zeros() as usize,
+            None => 0,
         }
+    }
+
+    fn union_with(&mut self, other: &Self) {
+        if self.blocks.len() < other.blocks.len() {
+            self.blocks.resize(other.blocks.len(), 0);
+        }
+
+        self.blocks.iter_mut().zip(other.blocks.iter()).for_each(|(x, y)| *x |= y);
+    }
 
-        digit <<= 1;
-    });
+    fn intersects(&self, other: &Self) -> bool {
+        self.blocks.iter().zip(other.blocks.iter()).any(|(x, y)| x & y != 0)
+    }
+
+    fn increment(&mut self) {
+        for block in self.blocks.iter_mut() {
+            let (sum, carry) = block.overflowing_add(1);
+            *block = sum;
+
+            if !carry {
+                return;
+            }
+        }
+
+        self.blocks.push(1);
+    }
+}
+
+pub fn generate_mask<S: BitSet>(indices: &[usize]) -> S {
+    let mut mask = S::empty();
+
+    for &index in indices {
+        mask.insert(index);
+    }
+
+    mask
+}
 
-    indices
+pub fn generate_indices<S: BitSet>(mask: &S) -> Vec<usize> {
+    (0..mask.bit_len()).filter(|&i| mask.contains(i)).collect()
 }
 
-pub fn generate_vectors_from_indices(indices: &Vec<usize>) -> Vec<usize> {
-    indices.iter().map(|i| USIZE_BASIS[*i]).collect()
+pub fn generate_vectors_from_indices<S: BitSet>(indices: &[usize]) -> Vec<S> {
+    indices.iter().map(|&i| S::singleton(i)).collect()
 }
 
-pub fn generate_vectors_from_mask(mask: usize) -> Vec<usize> {
+pub fn generate_vectors_from_mask<S: BitSet>(mask: &S) -> Vec<S> {
     let indices = generate_indices(mask);
     generate_vectors_from_indices(&indices)
 }
@@ -1,16 +1,24 @@
-const PRECISION : f64 = 1.0e-7;
+pub const DEFAULT_TOLERANCE: f64 = 1.0e-7;
 
 pub enum Comparison {
     AlmostEqual,
     NotEqual(f64),
 }
 
-pub fn compare_roughly(left: f64, right: f64) -> Comparison {
+pub fn compare_within(left: f64, right: f64, tolerance: f64) -> Comparison {
     let diff = left - right;
 
-    if diff.abs() < PRECISION {
+    if diff.abs() < tolerance {
         return Comparison::AlmostEqual;
     }
 
     Comparison::NotEqual(diff)
 }
+
+pub fn compare_roughly(left: f64, right: f64) -> Comparison {
+    compare_within(left, right, DEFAULT_TOLERANCE)
+}
+
+pub fn is_almost_equal(left: f64, right: f64, tolerance: f64) -> bool {
+    matches!(compare_within(left, right, tolerance), Comparison::AlmostEqual)
+}
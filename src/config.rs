@@ -0,0 +1,106 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::{compare::DEFAULT_TOLERANCE, emd::DistanceMeasure, error::{PhiError, Result}, partition::{MechanismPartitionScheme, SystemPartitionScheme}, tpm::BackgroundCondition};
+
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AnalysisConfig {
+    pub system_scheme: SystemPartitionScheme,
+    pub mechanism_scheme: MechanismPartitionScheme,
+    pub report_ties: bool, // report all tied MIPs and core purviews
+    pub tolerance: f64, // differences of phi below this are ignored
+    pub distance: DistanceMeasure, // between repertoires
+    pub background: Vec<BackgroundCondition>, // indexed by element, `Current` if omitted
+    pub num_threads: usize,
+    pub log: bool,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        // same as IIT 3.0
+        AnalysisConfig {
+            system_scheme: SystemPartitionScheme::Unidirectional,
+            mechanism_scheme: MechanismPartitionScheme::Bipartition,
+            report_ties: false,
+            tolerance: DEFAULT_TOLERANCE,
+            distance: DistanceMeasure::Emd,
+            background: Vec::<BackgroundCondition>::new(),
+            num_threads: 1,
+            log: false,
+        }
+    }
+}
+
+impl AnalysisConfig {
+    pub fn builder() -> AnalysisConfigBuilder {
+        AnalysisConfigBuilder {
+            config: AnalysisConfig::default(),
+        }
+    }
+
+    // fields are public, so searches check again what `build` checks
+    pub fn validate(&self) -> Result<()> {
+        if self.num_threads == 0 {
+            return Err(PhiError::InvalidConfig("At least one thread is needed".to_string()));
+        }
+
+        if !(self.tolerance.is_finite() && self.tolerance > 0.0) {
+            return Err(PhiError::InvalidConfig(format!("Tolerance {} must be positive", self.tolerance)));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct AnalysisConfigBuilder {
+    config: AnalysisConfig,
+}
+
+impl AnalysisConfigBuilder {
+    pub fn system_scheme(mut self, scheme: SystemPartitionScheme) -> Self {
+        self.config.system_scheme = scheme;
+        self
+    }
+
+    pub fn mechanism_scheme(mut self, scheme: MechanismPartitionScheme) -> Self {
+        self.config.mechanism_scheme = scheme;
+        self
+    }
+
+    pub fn report_ties(mut self, report_ties: bool) -> Self {
+        self.config.report_ties = report_ties;
+        self
+    }
+
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.config.tolerance = tolerance;
+        self
+    }
+
+    pub fn distance(mut self, distance: DistanceMeasure) -> Self {
+        self.config.distance = distance;
+        self
+    }
+
+    pub fn background(mut self, background: &[BackgroundCondition]) -> Self {
+        self.config.background = background.to_vec();
+        self
+    }
+
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.config.num_threads = num_threads;
+        self
+    }
+
+    pub fn log(mut self, log: bool) -> Self {
+        self.config.log = log;
+        self
+    }
+
+    pub fn build(self) -> Result<AnalysisConfig> {
+        self.config.validate()?;
+
+        Ok(self.config)
+    }
+}
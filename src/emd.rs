@@ -1,15 +1,26 @@
 use nalgebra as na;
 use minilp::{LinearExpr, Problem};
 
-use crate::system::Constellation;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::{error::{PhiError, Result}, system::Constellation};
 
 
-fn check_dimension(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>) -> usize {
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DistanceMeasure {
+    Emd, // earth mover's distance over hamming distance of states, as IIT 3.0
+    L1,
+}
+
+fn check_dimension(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>) -> Result<usize> {
     let ndim = vec_from.len();
-    assert!(ndim > 0);
-    assert!(vec_to.len() == ndim);
 
-    ndim
+    if ndim == 0 || vec_to.len() != ndim {
+        return Err(PhiError::InvalidDimension { from: ndim, to: vec_to.len() });
+    }
+
+    Ok(ndim)
 }
 
 fn generate_empty_exprs(capacity: usize) -> Vec<LinearExpr> {
@@ -18,8 +29,8 @@ fn generate_empty_exprs(capacity: usize) -> Vec<LinearExpr> {
     vec
 }
 
-pub fn calc_repertoire_emd(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>) -> f64 {
-    let ndim = check_dimension(vec_from, vec_to);
+pub fn try_calc_repertoire_emd(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>) -> Result<f64> {
+    let ndim = check_dimension(vec_from, vec_to)?;
     let mut problem = Problem::new(minilp::OptimizationDirection::Minimize);
     let mut horizontal_sums = generate_empty_exprs(ndim);
     let mut vertical_sums = generate_empty_exprs(ndim);
@@ -37,15 +48,33 @@ pub fn calc_repertoire_emd(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64
     vec_from.iter().zip(horizontal_sums).for_each(|(&p, expr)| problem.add_constraint(expr, minilp::ComparisonOp::Eq, p));
     vec_to.iter().zip(vertical_sums).for_each(|(&q, expr)| problem.add_constraint(expr, minilp::ComparisonOp::Eq, q));
 
-    problem.solve().unwrap().objective()
+    Ok(problem.solve()?.objective())
 }
 
-pub fn calc_constellation_emd(constellation_from: &Constellation, constellation_to: &Constellation) -> f64 {
+fn try_calc_repertoire_l1(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>) -> Result<f64> {
+    check_dimension(vec_from, vec_to)?;
+
+    Ok(vec_from.iter().zip(vec_to.iter()).map(|(p, q)| (p - q).abs()).sum())
+}
+
+pub fn try_calc_repertoire_distance(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>, measure: DistanceMeasure) -> Result<f64> {
+    match measure {
+        DistanceMeasure::Emd => try_calc_repertoire_emd(vec_from, vec_to),
+        DistanceMeasure::L1 => try_calc_repertoire_l1(vec_from, vec_to),
+    }
+}
+
+pub fn try_calc_constellation_emd(constellation_from: &Constellation, constellation_to: &Constellation) -> Result<f64> {
+    try_calc_constellation_distance(constellation_from, constellation_to, DistanceMeasure::Emd)
+}
+
+// extended EMD whose ground distance between concepts is given by `measure`
+pub fn try_calc_constellation_distance(constellation_from: &Constellation, constellation_to: &Constellation, measure: DistanceMeasure) -> Result<f64> {
     let from_concepts_size = constellation_from.concepts.len();
     let to_concepts_size = constellation_to.concepts.len();
 
     if from_concepts_size == 0 {
-        return 0.0;
+        return Ok(0.0);
     }
 
     let total_from_phi = constellation_from.concepts.iter().fold(0.0, |acc, x| acc + x.phi);
@@ -57,20 +86,20 @@ pub fn calc_constellation_emd(constellation_from: &Constellation, constellation_
     let mut vertical_sums = generate_empty_exprs(to_concepts_size);
     let mut null_sum = LinearExpr::empty();
 
-    (0..from_concepts_size).for_each(|i| {
-        (0..to_concepts_size).for_each(|j| {
-            let d = constellation_from.concepts[i].distance_from(&constellation_to.concepts[j]);
+    for (concept_from, horizontal_sum) in constellation_from.concepts.iter().zip(horizontal_sums.iter_mut()) {
+        for (concept_to, vertical_sum) in constellation_to.concepts.iter().zip(vertical_sums.iter_mut()) {
+            let d = concept_from.try_distance_with(concept_to, measure)?;
             let e = problem.add_var(d, (0.0, f64::INFINITY));
 
-            horizontal_sums[i].add(e, 1.0);
-            vertical_sums[j].add(e, 1.0);
-        });
+            horizontal_sum.add(e, 1.0);
+            vertical_sum.add(e, 1.0);
+        }
 
-        let null_distance = constellation_from.concepts[i].distance_from(&constellation_to.null_concept);
+        let null_distance = concept_from.try_distance_with(&constellation_to.null_concept, measure)?;
         let null_earth = problem.add_var(null_distance, (0.0, f64::INFINITY));
         null_sum.add(null_earth, 1.0);
-        horizontal_sums[i].add(null_earth, 1.0);
-    });
+        horizontal_sum.add(null_earth, 1.0);
+    }
 
     constellation_from.concepts.iter().zip(horizontal_sums).for_each(|(concept, expr)| {
         problem.add_constraint(expr, minilp::ComparisonOp::Eq, concept.phi)
@@ -83,5 +112,21 @@ pub fn calc_constellation_emd(constellation_from: &Constellation, constellation_
     problem.add_constraint(null_sum, minilp::ComparisonOp::Eq, oversupply);
 
 
-    problem.solve().unwrap().objective()
+    Ok(problem.solve()?.objective())
+}
+
+pub fn calc_repertoire_emd(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>) -> f64 {
+    try_calc_repertoire_emd(vec_from, vec_to).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn calc_constellation_emd(constellation_from: &Constellation, constellation_to: &Constellation) -> f64 {
+    try_calc_constellation_emd(constellation_from, constellation_to).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn calc_repertoire_distance(vec_from: &na::DVector<f64>, vec_to: &na::DVector<f64>, measure: DistanceMeasure) -> f64 {
+    try_calc_repertoire_distance(vec_from, vec_to, measure).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn calc_constellation_distance(constellation_from: &Constellation, constellation_to: &Constellation, measure: DistanceMeasure) -> f64 {
+    try_calc_constellation_distance(constellation_from, constellation_to, measure).unwrap_or_else(|e| panic!("{}", e))
 }
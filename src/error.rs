@@ -0,0 +1,104 @@
+use std::{fmt, io};
+
+
+#[derive(Debug)]
+pub enum PhiError {
+    Io(io::Error),
+    Sif { file: Option<String>, line: usize, column: usize, message: String },
+    TpmFile { file: Option<String>, line: usize, column: usize, message: String },
+    InvalidTpm(String),
+    DuplicateElement(String),
+    UnknownElement(String),
+    InvalidState(String),
+    UndefinedElement { element: String, condition: String },
+    InvalidCondition { element: String, size: usize },
+    InvalidParameter { element: String, message: String },
+    InvalidSystemSize(usize),
+    InvalidConfig(String),
+    Serialization(String),
+    InvalidDimension { from: usize, to: usize },
+    WorkerPanicked,
+    Cancelled,
+    LpInfeasible,
+    LpUnbounded,
+}
+
+pub type Result<T> = std::result::Result<T, PhiError>;
+
+impl PhiError {
+    // attach the path to an error located in a file read from a reader
+    pub(crate) fn with_file(self, path: &str) -> PhiError {
+        match self {
+            PhiError::Sif { file: None, line, column, message } => {
+                PhiError::Sif { file: Some(path.to_string()), line, column, message }
+            },
+            PhiError::TpmFile { file: None, line, column, message } => {
+                PhiError::TpmFile { file: Some(path.to_string()), line, column, message }
+            },
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for PhiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhiError::Io(e) => write!(f, "I/O error: {}", e),
+            PhiError::Sif { file: Some(file), line, column, message } => {
+                write!(f, "SIF error at {}:{}:{}: {}", file, line, column, message)
+            },
+            PhiError::Sif { file: None, line, column, message } => {
+                write!(f, "SIF error at line {}, column {}: {}", line, column, message)
+            },
+            PhiError::TpmFile { file: Some(file), line, column, message } => {
+                write!(f, "TPM error at {}:{}:{}: {}", file, line, column, message)
+            },
+            PhiError::TpmFile { file: None, line, column, message } => {
+                write!(f, "TPM error at line {}, column {}: {}", line, column, message)
+            },
+            PhiError::InvalidTpm(message) => write!(f, "Invalid TPM: {}", message),
+            PhiError::DuplicateElement(element) => write!(f, "ELEMENT '{}' is defined twice or more", element),
+            PhiError::UnknownElement(element) => write!(f, "ELEMENT '{}' is not in the network", element),
+            PhiError::InvalidState(message) => write!(f, "Invalid state: {}", message),
+            PhiError::UndefinedElement { element, condition } => {
+                write!(f, "ELEMENT '{}' has condition '{}' whose element is not defined", element, condition)
+            },
+            PhiError::InvalidCondition { element, size } => {
+                write!(f, "ELEMENT '{}' has not-implemented link type or invalid condition size {}", element, size)
+            },
+            PhiError::InvalidParameter { element, message } => write!(f, "ELEMENT '{}' has invalid parameter: {}", element, message),
+            PhiError::InvalidSystemSize(size) => write!(f, "System of {} elements is not supported", size),
+            PhiError::InvalidConfig(message) => write!(f, "Invalid analysis configuration: {}", message),
+            PhiError::Serialization(message) => write!(f, "Serialization error: {}", message),
+            PhiError::InvalidDimension { from, to } => write!(f, "Repertoires of {} and {} states cannot be compared", from, to),
+            PhiError::WorkerPanicked => write!(f, "Some worker thread panicked"),
+            PhiError::Cancelled => write!(f, "Analysis was cancelled"),
+            PhiError::LpInfeasible => write!(f, "EMD problem is infeasible"),
+            PhiError::LpUnbounded => write!(f, "EMD problem is unbounded"),
+        }
+    }
+}
+
+impl std::error::Error for PhiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PhiError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PhiError {
+    fn from(e: io::Error) -> Self {
+        PhiError::Io(e)
+    }
+}
+
+impl From<minilp::Error> for PhiError {
+    fn from(e: minilp::Error) -> Self {
+        match e {
+            minilp::Error::Infeasible => PhiError::LpInfeasible,
+            minilp::Error::Unbounded => PhiError::LpUnbounded,
+        }
+    }
+}
@@ -1,5 +1,8 @@
+pub mod error;
+mod tokenize;
 pub mod sif;
 pub mod tpm;
+pub mod tpm_file;
 pub mod link_fn;
 pub mod bitwise;
 pub mod compare;
@@ -9,6 +12,11 @@ pub mod repertoire;
 pub mod partition;
 pub mod mechanism;
 pub mod system;
+pub mod config;
+pub mod network;
+pub mod progress;
+#[cfg(feature = "serde")]
+pub mod serialize;
 
 #[cfg(test)]
 pub mod tests;
@@ -1,10 +1,23 @@
 use std::collections::HashMap;
-use crate::{bitwise::generate_mask, sif::{LinkType, LinkInfo}};
+use crate::{bitwise::{USIZE_BITS, generate_mask}, error::{PhiError, Result}, sif::{LinkType, LinkInfo}, tpm::{LinkModel, LocalLink}};
 
 
 pub type LinkFn = fn(env: usize, mask: usize) -> f64;
 // return probability that the future state of the element is ON
 
+pub trait Link: Send + Sync {
+    // same as `LinkFn`, but can carry parameters of the mechanism
+    fn probability(&self, env: usize, mask: usize) -> f64;
+}
+
+impl <F: Fn(usize, usize) -> f64 + Send + Sync> Link for F {
+    fn probability(&self, env: usize, mask: usize) -> f64 {
+        self(env, mask)
+    }
+}
+
+pub type BoxedLink = Box<dyn Link>;
+
 fn to_float(b: bool) -> f64 {
     if b {
         1.0
@@ -31,7 +44,28 @@ fn link_or(env: usize, mask: usize) -> f64 {
 }
 
 fn link_xor(env: usize, mask: usize) -> f64 {
-    to_float((env & mask).count_ones() == 1)
+    // odd parity, which is "exactly one" for 2 inputs
+    to_float((env & mask).count_ones() % 2 == 1)
+}
+
+fn link_nand(env: usize, mask: usize) -> f64 {
+    to_float(env & mask != mask)
+}
+
+fn link_nor(env: usize, mask: usize) -> f64 {
+    to_float(env & mask == 0)
+}
+
+fn link_xnor(env: usize, mask: usize) -> f64 {
+    to_float((env & mask).count_ones().is_multiple_of(2))
+}
+
+fn link_maj(env: usize, mask: usize) -> f64 {
+    to_float(2 * (env & mask).count_ones() > mask.count_ones())
+}
+
+fn link_min(env: usize, mask: usize) -> f64 {
+    to_float(2 * (env & mask).count_ones() < mask.count_ones())
 }
 
 fn link_any(env: usize, mask: usize) -> f64 {
@@ -43,7 +77,7 @@ fn link_all(env: usize, mask: usize) -> f64 {
 }
 
 fn link_even(env: usize, mask: usize) -> f64 {
-    to_float((env & mask).count_ones() % 2 == 0)
+    to_float((env & mask).count_ones().is_multiple_of(2))
 }
 
 fn link_odd(env: usize, mask: usize) -> f64 {
@@ -54,37 +88,186 @@ fn link_noisy(_env: usize, _mask: usize) -> f64 {
     0.5
 }
 
+fn calc_weighted_sum(env: usize, inputs: &[(usize, f64)]) -> f64 {
+    inputs.iter().filter(|(index, _)| env & (1 << index) != 0).map(|(_, weight)| weight).sum()
+}
 
-pub fn get_link_fn(link: &LinkType, size: usize) -> LinkFn {
+pub struct ThresholdLink {
+    pub inputs: Vec<(usize, f64)>, // pairs of element index and weight
+    pub threshold: f64,
+}
+
+impl Link for ThresholdLink {
+    fn probability(&self, env: usize, _mask: usize) -> f64 {
+        to_float(calc_weighted_sum(env, &self.inputs) > self.threshold)
+    }
+}
+
+pub struct SigmoidLink {
+    pub inputs: Vec<(usize, f64)>, // pairs of element index and weight
+    pub threshold: f64,
+    pub temperature: f64,
+}
+
+impl Link for SigmoidLink {
+    fn probability(&self, env: usize, _mask: usize) -> f64 {
+        let x = (calc_weighted_sum(env, &self.inputs) - self.threshold) / self.temperature;
+        1.0 / (1.0 + (-x).exp())
+    }
+}
+
+pub struct TableLink {
+    pub inputs: Vec<usize>,
+    pub probabilities: Vec<f64>, // indexed by the states of `inputs`, the first input is the lowest bit
+}
+
+impl Link for TableLink {
+    fn probability(&self, env: usize, _mask: usize) -> f64 {
+        let row = self.inputs.iter().enumerate().fold(0, |acc, (i, &input)| {
+            acc | (((env >> input) & 1) << i)
+        });
+
+        self.probabilities[row]
+    }
+}
+
+pub struct NoisyLink {
+    pub link: BoxedLink,
+    pub fidelity: f64, // probability to follow `link`, flipped otherwise
+}
+
+impl Link for NoisyLink {
+    fn probability(&self, env: usize, mask: usize) -> f64 {
+        let p = self.link.probability(env, mask);
+        self.fidelity * p + (1.0 - self.fidelity) * (1.0 - p)
+    }
+}
+
+
+fn select_link_fn(link: &LinkType, size: usize) -> Option<LinkFn> {
     match link {
-        LinkType::COPY if size == 1 => link_copy,
-        LinkType::NOT if size == 1 => link_not,
-        LinkType::AND if size == 2 => link_and,
-        LinkType::OR if size == 2 => link_or,
-        LinkType::XOR if size == 2 => link_xor,
-        LinkType::ANY if size > 0 => link_any,
-        LinkType::ALL if size > 0 => link_all,
-        LinkType::EVEN if size > 0 => link_even,
-        LinkType::ODD if size > 0 => link_odd,
-        LinkType::NOISY if size > 0 => link_noisy,
-
-        _ => panic!("Not-implemented link type or invalid condition size"),
+        LinkType::COPY if size == 1 => Some(link_copy),
+        LinkType::NOT if size == 1 => Some(link_not),
+        LinkType::AND if size > 0 => Some(link_and),
+        LinkType::OR if size > 0 => Some(link_or),
+        LinkType::XOR if size > 0 => Some(link_xor),
+        LinkType::NAND if size > 0 => Some(link_nand),
+        LinkType::NOR if size > 0 => Some(link_nor),
+        LinkType::XNOR if size > 0 => Some(link_xnor),
+        LinkType::MAJ if size > 0 => Some(link_maj),
+        LinkType::MIN if size > 0 => Some(link_min),
+        LinkType::ANY if size > 0 => Some(link_any),
+        LinkType::ALL if size > 0 => Some(link_all),
+        LinkType::EVEN if size > 0 => Some(link_even),
+        LinkType::ODD if size > 0 => Some(link_odd),
+        LinkType::NOISY if size > 0 => Some(link_noisy),
+
+        _ => None,
     }
 }
 
-pub fn get_link_fns(infos: Vec<LinkInfo>) -> Vec<(LinkFn, usize)> {
+pub fn get_link_fn(link: &LinkType, size: usize) -> LinkFn {
+    select_link_fn(link, size).expect("Not-implemented link type or invalid condition size")
+}
+
+fn parameter_error(info: &LinkInfo, message: &str) -> PhiError {
+    PhiError::InvalidParameter { element: info.element.clone(), message: message.to_string() }
+}
+
+fn check_probability(info: &LinkInfo, p: f64) -> Result<f64> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(p)
+    } else {
+        Err(parameter_error(info, &format!("Probability {} is out of [0, 1]", p)))
+    }
+}
+
+fn build_link(info: &LinkInfo, indices: &[usize]) -> Result<BoxedLink> {
+    let is_weighted = matches!(info.link_type, LinkType::THRESHOLD | LinkType::SIGMOID);
+    if !is_weighted && info.weights.iter().any(|&w| w != 1.0) {
+        return Err(parameter_error(info, "Weights are allowed only for THRESHOLD and SIGMOID"));
+    }
+
+    let inputs: Vec<(usize, f64)> = indices.iter().copied().zip(info.weights.iter().copied()).collect();
+
+    let (link, rest): (BoxedLink, &[f64]) = match info.link_type {
+        LinkType::THRESHOLD => {
+            let (&threshold, rest) = info.parameters.split_first().ok_or_else(|| {
+                parameter_error(info, "THRESHOLD needs a threshold")
+            })?;
+
+            (Box::new(ThresholdLink { inputs, threshold }), rest)
+        },
+        LinkType::SIGMOID => {
+            let (threshold, temperature, rest) = match info.parameters[..] {
+                [threshold, temperature, ref rest @ ..] => (threshold, temperature, rest),
+                _ => return Err(parameter_error(info, "SIGMOID needs a threshold and a temperature")),
+            };
+
+            if temperature <= 0.0 {
+                return Err(parameter_error(info, "Temperature must be positive"));
+            }
+
+            (Box::new(SigmoidLink { inputs, threshold, temperature }), rest)
+        },
+        LinkType::TABLE => {
+            if info.parameters.len() != 1 << indices.len() {
+                return Err(parameter_error(info, &format!("TABLE needs {} probabilities", 1 << indices.len())));
+            }
+
+            let probabilities = info.parameters.iter().map(|&p| check_probability(info, p)).collect::<Result<Vec<f64>>>()?;
+
+            return Ok(Box::new(TableLink { inputs: indices.to_vec(), probabilities }));
+        },
+        LinkType::NOISY if info.parameters.len() == 1 => {
+            // `NOISY:p` means ON with probability p
+            let p = check_probability(info, info.parameters[0])?;
+
+            return Ok(Box::new(move |_env: usize, _mask: usize| p));
+        },
+        _ => {
+            let link_fn = select_link_fn(&info.link_type, indices.len()).ok_or_else(|| {
+                PhiError::InvalidCondition { element: info.element.clone(), size: indices.len() }
+            })?;
+
+            (Box::new(link_fn), &info.parameters[..])
+        },
+    };
+
+    match rest {
+        [] => Ok(link),
+        [fidelity] => {
+            let fidelity = check_probability(info, *fidelity)?;
+
+            Ok(Box::new(NoisyLink { link, fidelity }))
+        },
+        _ => Err(parameter_error(info, &format!("Too many parameters for {}", info.link_type))),
+    }
+}
+
+fn generate_element_indices(infos: &[LinkInfo]) -> Result<HashMap<String, usize>> {
     let mut to_index = HashMap::<String, usize>::new();
 
     for (i, info) in infos.iter().enumerate() {
         let result = to_index.insert(info.element.clone(), i);
 
         if result.is_some() {
-            panic!("Some element is defined twice or more: {:?}", info);
+            return Err(PhiError::DuplicateElement(info.element.clone()));
         }
     }
 
+    Ok(to_index)
+}
+
+pub fn try_get_link_fns(infos: Vec<LinkInfo>) -> Result<Vec<(BoxedLink, usize)>> {
+    // masks are `usize`, larger networks are read by `try_get_link_model`
+    if infos.len() >= USIZE_BITS {
+        return Err(PhiError::InvalidSystemSize(infos.len()));
+    }
 
-    let mut fns = Vec::<(LinkFn, usize)>::new();
+    let to_index = generate_element_indices(&infos)?;
+
+    let mut fns = Vec::<(BoxedLink, usize)>::new();
 
     for info in infos.iter() {
         let mut indices = Vec::<usize>::new();
@@ -95,15 +278,54 @@ pub fn get_link_fns(infos: Vec<LinkInfo>) -> Vec<(LinkFn, usize)> {
             if let Some(x) = index {
                 indices.push(*x);
             } else {
-                panic!("ELEMENT '{}' has condition whose element is not defined", info.element);
+                return Err(PhiError::UndefinedElement { element: info.element.clone(), condition: c.clone() });
             }
         }
 
-        let link_fn = get_link_fn(&info.link_type, indices.len());
+        let link = build_link(info, &indices)?;
         let mask = generate_mask(&indices);
 
-        fns.push((link_fn, mask));
+        fns.push((link, mask));
+    }
+
+    Ok(fns)
+}
+
+// links read their inputs as the lowest bits, so that the network size is not limited by `usize`
+pub fn try_get_link_model(infos: Vec<LinkInfo>) -> Result<LinkModel> {
+    let to_index = generate_element_indices(&infos)?;
+
+    let mut links = Vec::<LocalLink>::new();
+
+    for info in infos.iter() {
+        let mut inputs = Vec::<usize>::new();
+        let mut local_indices = Vec::<usize>::new();
+
+        for c in info.condition.iter() {
+            let index = *to_index.get(c).ok_or_else(|| {
+                PhiError::UndefinedElement { element: info.element.clone(), condition: c.clone() }
+            })?;
+
+            let local_index = inputs.iter().position(|&x| x == index).unwrap_or_else(|| {
+                inputs.push(index);
+                inputs.len() - 1
+            });
+
+            local_indices.push(local_index);
+        }
+
+        if inputs.len() >= USIZE_BITS {
+            return Err(PhiError::InvalidCondition { element: info.element.clone(), size: inputs.len() });
+        }
+
+        let link = build_link(info, &local_indices)?;
+
+        links.push(LocalLink { inputs, link });
     }
 
-    fns
+    Ok(LinkModel { links })
+}
+
+pub fn get_link_fns(infos: Vec<LinkInfo>) -> Vec<(BoxedLink, usize)> {
+    try_get_link_fns(infos).unwrap_or_else(|e| panic!("{}", e))
 }
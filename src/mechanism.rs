@@ -1,5 +1,8 @@
+use std::fmt;
 use nalgebra as na;
-use crate::{basis::BitBasis, compare::{Comparison, compare_roughly}, emd::calc_repertoire_emd, partition::{MechanismPartition, MechanismPartitionIterator}, repertoire::{calc_cause_repertoire, calc_effect_repertoire}};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::{basis::BitBasis, compare::{Comparison, compare_within, is_almost_equal}, config::AnalysisConfig, emd::{DistanceMeasure, try_calc_repertoire_distance}, error::Result, network::{format_repertoire, generate_default_names}, partition::{MechanismPartition, generate_mechanism_partitions}, repertoire::{calc_cause_repertoire, calc_effect_repertoire}, tpm::TransitionModel};
 
 
 pub enum RepertoireType {
@@ -7,15 +10,15 @@ pub enum RepertoireType {
     EFFECT,
 }
 
-pub fn generate_all_repertoire_parts(repertoire_type: RepertoireType, current_state: usize, tpm: &na::DMatrix<f64>) -> na::DMatrix<f64> {
-    let ndim = tpm.nrows();
+pub fn generate_all_repertoire_parts<T: TransitionModel + ?Sized>(repertoire_type: RepertoireType, current_state: usize, tpm: &T) -> na::DMatrix<f64> {
+    let ndim = tpm.state_size();
     let combination_ndim = ndim * ndim;
 
     let mut result = na::DMatrix::<f64>::zeros(combination_ndim, ndim);
 
     let calc_repertoire = match repertoire_type {
-        RepertoireType::CAUSE => calc_cause_repertoire,
-        RepertoireType::EFFECT => calc_effect_repertoire,
+        RepertoireType::CAUSE => calc_cause_repertoire::<T>,
+        RepertoireType::EFFECT => calc_effect_repertoire::<T>,
     };
 
     let max_dim = (ndim - 1).count_ones() as usize;
@@ -33,19 +36,38 @@ pub fn generate_all_repertoire_parts(repertoire_type: RepertoireType, current_st
     result
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CoreRepertoire {
     pub purview: BitBasis,
     pub repertoire: na::DVector<f64>,
     pub partition: MechanismPartition,
     pub phi: f64,
+    pub tied_purviews: Vec<BitBasis>, // filled only when ties are reported, including `purview`
+    pub tied_partitions: Vec<MechanismPartition>, // same as above, including `partition`
+}
+
+impl fmt::Debug for CoreRepertoire {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the repertoire is shown as a table of states
+        let names = generate_default_names(self.repertoire.len().trailing_zeros() as usize);
+
+        f.debug_struct("CoreRepertoire")
+            .field("purview", &self.purview)
+            .field("repertoire", &format_args!("\n{}\n", format_repertoire(&self.repertoire, &names)))
+            .field("partition", &self.partition)
+            .field("phi", &self.phi)
+            .field("tied_purviews", &self.tied_purviews)
+            .field("tied_partitions", &self.tied_partitions)
+            .finish()
+    }
 }
 
 pub fn construct_vector_from_row(row: usize, matrix: &na::DMatrix<f64>) -> na::DVector<f64> {
-    na::DVector::<f64>::from_iterator(matrix.ncols(), matrix.row(row).iter().map(|x| *x))
+    na::DVector::<f64>::from_iterator(matrix.ncols(), matrix.row(row).iter().copied())
 }
 
-pub fn search_core_with_parts(mechanism: &BitBasis, parts: &na::DMatrix<f64>) -> CoreRepertoire {
+pub fn try_search_core_with_parts(mechanism: &BitBasis, parts: &na::DMatrix<f64>, config: &AnalysisConfig) -> Result<CoreRepertoire> {
+    let tolerance = config.tolerance;
     let mechanism_mask = mechanism.to_mask();
 
     let unconstrained_row = !(usize::MAX << mechanism.max_dim) << mechanism.max_dim;
@@ -56,12 +78,20 @@ pub fn search_core_with_parts(mechanism: &BitBasis, parts: &na::DMatrix<f64>) ->
         repertoire: unconstrained,
         partition: MechanismPartition::null_partition(),
         phi: 0.0,
+        tied_purviews: Vec::<BitBasis>::new(),
+        tied_partitions: Vec::<MechanismPartition>::new(),
     };
 
+    // candidates which were almost equal to or better than the best one when found
+    let mut tied_purviews = Vec::<(f64, BitBasis)>::new();
+
     for purview_mask in 0..mechanism.max_image_size() {
         let candidate = BitBasis::construct_from_mask(purview_mask, mechanism.max_dim);
         if candidate.dim + mechanism.dim == 1 {
             // No possible partition
+            if config.report_ties {
+                tied_purviews.push((0.0, candidate));
+            }
             continue;
         }
 
@@ -76,54 +106,80 @@ pub fn search_core_with_parts(mechanism: &BitBasis, parts: &na::DMatrix<f64>) ->
 
         let mut min_emd = f64::INFINITY;
         let mut mip = MechanismPartition::null_partition();
+        let mut tied_partitions = Vec::<(f64, MechanismPartition)>::new();
 
-        let partitions = MechanismPartitionIterator::construct(candidate.dim, mechanism.dim);
-        for partition in partitions {
-            let left_purview_mask = candidate.sub_basis(&partition.left_purview).to_mask() << mechanism.max_dim;
-            let right_purview_mask = candidate.sub_basis(&partition.right_purview).to_mask() << mechanism.max_dim;
-            let left_mechanism_mask = mechanism.sub_basis(&partition.left_mechanism).to_mask();
-            let right_mechanism_mask = mechanism.sub_basis(&partition.right_mechanism).to_mask();
-
+        for partition in generate_mechanism_partitions(config.mechanism_scheme, candidate.dim, mechanism.dim) {
             let mut joint = unconstrained_part.clone();
-            joint.component_mul_assign(&construct_vector_from_row(left_purview_mask | left_mechanism_mask, parts));
-            joint.component_mul_assign(&construct_vector_from_row(right_purview_mask | right_mechanism_mask, parts));
+            partition.parts.iter().for_each(|part| {
+                let purview_mask = candidate.sub_basis(&part.purview).to_mask() << mechanism.max_dim;
+                let mechanism_mask = mechanism.sub_basis(&part.mechanism).to_mask();
+
+                joint.component_mul_assign(&construct_vector_from_row(purview_mask | mechanism_mask, parts));
+            });
 
-            let emd = calc_repertoire_emd(&criterion, &joint);
+            let emd = try_calc_repertoire_distance(&criterion, &joint, config.distance)?;
             if emd < min_emd {
                 min_emd = emd;
-                mip = partition;
+                mip = partition.clone();
             };
 
-            if let Comparison::AlmostEqual = compare_roughly(min_emd, 0.0) {
-                min_emd = 0.0;
+            if config.report_ties {
+                // the same partition can be found in another order of parts
+                let is_new = !tied_partitions.iter().any(|(_, tied)| tied.is_equivalent(&partition));
+                if is_almost_equal(emd, min_emd, tolerance) && is_new {
+                    tied_partitions.push((emd, partition));
+                }
+            } else if is_almost_equal(min_emd, 0.0, tolerance) {
                 break;
             }
         }
 
-        if min_emd == f64::INFINITY { // no possible partition found
+        let tied_partitions: Vec<MechanismPartition> = tied_partitions.into_iter()
+            .filter(|(emd, _)| is_almost_equal(*emd, min_emd, tolerance))
+            .map(|(_, partition)| partition)
+            .collect();
+
+        if min_emd == f64::INFINITY || is_almost_equal(min_emd, 0.0, tolerance) { // no possible partition found or reducible
             min_emd = 0.0;
         }
 
-        let update = if let Comparison::NotEqual(diff) = compare_roughly(min_emd, max_phi_repertoire.phi) {
+        let update = if let Comparison::NotEqual(diff) = compare_within(min_emd, max_phi_repertoire.phi, tolerance) {
             diff.is_sign_positive()
         } else {
             candidate.dim > max_phi_repertoire.purview.dim
         };
 
+        let not_worse = !matches!(compare_within(min_emd, max_phi_repertoire.phi, tolerance), Comparison::NotEqual(diff) if diff.is_sign_negative());
+        if config.report_ties && not_worse {
+            tied_purviews.push((min_emd, candidate.clone()));
+        }
+
         if update {
             max_phi_repertoire = CoreRepertoire {
                 purview: candidate,
                 repertoire: criterion,
                 partition: mip,
                 phi: min_emd,
+                tied_purviews: Vec::<BitBasis>::new(),
+                tied_partitions,
             };
         }
     };
 
-    max_phi_repertoire
+    max_phi_repertoire.tied_purviews = tied_purviews.into_iter()
+        .filter(|(phi, _)| is_almost_equal(*phi, max_phi_repertoire.phi, tolerance))
+        .map(|(_, purview)| purview)
+        .collect();
+
+    Ok(max_phi_repertoire)
+}
+
+pub fn search_core_with_parts(mechanism: &BitBasis, parts: &na::DMatrix<f64>, config: &AnalysisConfig) -> CoreRepertoire {
+    try_search_core_with_parts(mechanism, parts, config).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Concept {
     pub mechanism: BitBasis,
     pub core_cause: CoreRepertoire,
@@ -132,25 +188,37 @@ pub struct Concept {
 }
 
 impl Concept {
-    pub fn distance_from(&self, other: &Concept) -> f64 {
-        let mut distance = calc_repertoire_emd(&self.core_cause.repertoire, &other.core_cause.repertoire);
+    pub fn try_distance_with(&self, other: &Concept, measure: DistanceMeasure) -> Result<f64> {
+        let mut distance = try_calc_repertoire_distance(&self.core_cause.repertoire, &other.core_cause.repertoire, measure)?;
 
-        distance += calc_repertoire_emd(&self.core_effect.repertoire, &other.core_effect.repertoire);
+        distance += try_calc_repertoire_distance(&self.core_effect.repertoire, &other.core_effect.repertoire, measure)?;
 
-        distance
+        Ok(distance)
+    }
+
+    pub fn try_distance_from(&self, other: &Concept) -> Result<f64> {
+        self.try_distance_with(other, DistanceMeasure::Emd)
+    }
+
+    pub fn distance_from(&self, other: &Concept) -> f64 {
+        self.try_distance_from(other).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
-pub fn search_concept_with_parts(mechanism: &BitBasis, cause_parts: &na::DMatrix<f64>, effect_parts: &na::DMatrix<f64>) -> Concept {
-    let core_cause = search_core_with_parts(mechanism, cause_parts);
-    let core_effect = search_core_with_parts(mechanism, effect_parts);
+pub fn try_search_concept_with_parts(mechanism: &BitBasis, cause_parts: &na::DMatrix<f64>, effect_parts: &na::DMatrix<f64>, config: &AnalysisConfig) -> Result<Concept> {
+    let core_cause = try_search_core_with_parts(mechanism, cause_parts, config)?;
+    let core_effect = try_search_core_with_parts(mechanism, effect_parts, config)?;
 
     let phi = core_cause.phi.min(core_effect.phi);
 
-    Concept {
+    Ok(Concept {
         mechanism: mechanism.clone(),
-        core_cause: core_cause,
-        core_effect: core_effect,
-        phi: phi,
-    }
+        core_cause,
+        core_effect,
+        phi,
+    })
+}
+
+pub fn search_concept_with_parts(mechanism: &BitBasis, cause_parts: &na::DMatrix<f64>, effect_parts: &na::DMatrix<f64>, config: &AnalysisConfig) -> Concept {
+    try_search_concept_with_parts(mechanism, cause_parts, effect_parts, config).unwrap_or_else(|e| panic!("{}", e))
 }
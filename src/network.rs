@@ -0,0 +1,281 @@
+use std::{collections::HashSet, fmt};
+use nalgebra as na;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::{basis::BitBasis, bitwise::generate_indices, error::{PhiError, Result}, link_fn::try_get_link_fns, mechanism::{Concept, CoreRepertoire}, partition::SystemCut, sif::{LinkInfo, try_read_sif}, system::{Complex, Constellation, MinimumInformationPartition}, tpm::try_calc_tpm, tpm_file::{TpmFormat, read_tpm}};
+
+
+#[derive(Debug, Clone)]
+pub struct Network {
+    pub names: Vec<String>, // the i-th name is the i-th element, the i-th bit of states
+    pub tpm: na::DMatrix<f64>,
+}
+
+pub fn generate_default_names(size: usize) -> Vec<String> {
+    // A, B, ..., Z, and N26, N27, ... for larger networks
+    (0..size).map(|i| {
+        if i < 26 {
+            ((b'A' + i as u8) as char).to_string()
+        } else {
+            format!("N{}", i)
+        }
+    }).collect()
+}
+
+impl Network {
+    pub fn construct(names: Vec<String>, tpm: na::DMatrix<f64>) -> Result<Network> {
+        if !tpm.nrows().is_power_of_two() || tpm.nrows() != tpm.ncols() {
+            return Err(PhiError::InvalidTpm(format!("{}x{} is not a state-by-state TPM", tpm.nrows(), tpm.ncols())));
+        }
+
+        let size = tpm.nrows().trailing_zeros() as usize;
+        if names.len() != size {
+            return Err(PhiError::InvalidTpm(format!("{} names are given for {} elements", names.len(), size)));
+        }
+
+        let mut found = HashSet::<&String>::new();
+        if let Some(name) = names.iter().find(|&name| !found.insert(name)) {
+            return Err(PhiError::DuplicateElement(name.clone()));
+        }
+
+        Ok(Network { names, tpm })
+    }
+
+    pub fn from_tpm(tpm: na::DMatrix<f64>) -> Result<Network> {
+        let names = generate_default_names(tpm.nrows().trailing_zeros() as usize);
+        Network::construct(names, tpm)
+    }
+
+    pub fn from_link_infos(infos: Vec<LinkInfo>, num_threads: usize) -> Result<Network> {
+        // the same order as `try_get_link_fns`
+        let names: Vec<String> = infos.iter().map(|info| info.element.clone()).collect();
+        let tpm = try_calc_tpm(try_get_link_fns(infos)?, num_threads)?;
+
+        Network::construct(names, tpm)
+    }
+
+    pub fn read_sif(path: &str, num_threads: usize) -> Result<Network> {
+        Network::from_link_infos(try_read_sif(path)?, num_threads)
+    }
+
+    pub fn read_tpm(path: &str, format: TpmFormat) -> Result<Network> {
+        Network::from_tpm(read_tpm(path, format)?)
+    }
+
+    pub fn size(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn element_index(&self, name: &str) -> Result<usize> {
+        self.names.iter().position(|x| x == name).ok_or_else(|| PhiError::UnknownElement(name.to_string()))
+    }
+
+    pub fn parse_state(&self, text: &str) -> Result<usize> {
+        parse_state(text, &self.names)
+    }
+
+    pub fn format_state(&self, state: usize) -> String {
+        format_state(state, &self.names)
+    }
+
+    pub fn format_repertoire(&self, repertoire: &na::DVector<f64>) -> String {
+        format_repertoire(repertoire, &self.names)
+    }
+
+    pub fn named<T>(&self, value: T) -> Named<T> {
+        Named { names: self.names.clone(), value }
+    }
+}
+
+pub fn parse_state(text: &str, names: &[String]) -> Result<usize> {
+    // either `A=1 B=0 C=0` or `100` in the order of elements, every element must be given
+    let text = text.trim();
+
+    if !text.is_empty() && text.chars().all(|c| c == '0' || c == '1') {
+        if text.len() != names.len() {
+            return Err(PhiError::InvalidState(format!("{} values are given for {} elements", text.len(), names.len())));
+        }
+
+        return Ok(text.chars().enumerate().filter(|&(_, c)| c == '1').fold(0, |state, (i, _)| state | (1 << i)));
+    }
+
+    let mut state = 0;
+    let mut given = vec![false; names.len()];
+
+    for token in text.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty()) {
+        let (name, value) = token.split_once('=').ok_or_else(|| PhiError::InvalidState(format!("'{}' is not given as NAME=0 or NAME=1", token)))?;
+        let index = names.iter().position(|x| x == name).ok_or_else(|| PhiError::UnknownElement(name.to_string()))?;
+
+        if given[index] {
+            return Err(PhiError::InvalidState(format!("State of '{}' is given twice", name)));
+        }
+
+        match value {
+            "0" => (),
+            "1" => state |= 1 << index,
+            _ => return Err(PhiError::InvalidState(format!("State of '{}' must be 0 or 1", name))),
+        }
+
+        given[index] = true;
+    }
+
+    match given.iter().position(|&x| !x) {
+        Some(index) => Err(PhiError::InvalidState(format!("State of '{}' is not given", names[index]))),
+        None => Ok(state),
+    }
+}
+
+pub fn format_state(state: usize, names: &[String]) -> String {
+    let states: Vec<String> = names.iter().enumerate().map(|(i, name)| format!("{}={}", name, (state >> i) & 1)).collect();
+    states.join(" ")
+}
+
+pub fn format_state_bits(state: usize, size: usize) -> String {
+    // the first character is the first element, unlike binary literals
+    (0..size).map(|i| if (state >> i) & 1 == 1 { '1' } else { '0' }).collect()
+}
+
+pub fn format_repertoire(repertoire: &na::DVector<f64>, names: &[String]) -> String {
+    // one row per state, written as `parse_state` accepts
+    let header = format_elements(&(0..names.len()).collect::<Vec<usize>>(), names);
+    let width = header.chars().count().max(names.len());
+
+    let mut table = format!("{:<width$}  P", header, width = width);
+    repertoire.iter().enumerate().for_each(|(state, p)| {
+        table += &format!("\n{:<width$}  {:.4}", format_state_bits(state, names.len()), p, width = width);
+    });
+
+    table
+}
+
+// result with the names of its elements, to be displayed or serialized
+// serialized values keep indices, which are resolved as `names[i]` by readers
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Named<T> {
+    pub names: Vec<String>,
+    pub value: T,
+}
+
+pub trait NamedDisplay {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+impl <T: NamedDisplay + ?Sized> NamedDisplay for &T {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt_named(names, f)
+    }
+}
+
+impl <T: NamedDisplay> fmt::Display for Named<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt_named(&self.names, f)
+    }
+}
+
+pub fn format_elements(indices: &[usize], names: &[String]) -> String {
+    // `AB` if every name is a single character, `Left,Right` otherwise
+    if indices.is_empty() {
+        return "[]".to_string();
+    }
+
+    let elements: Vec<&str> = indices.iter().map(|&i| names[i].as_str()).collect();
+    let separator = if elements.iter().all(|x| x.chars().count() == 1) { "" } else { "," };
+
+    elements.join(separator)
+}
+
+impl NamedDisplay for BitBasis {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_elements(&generate_indices(&self.to_mask()), names))
+    }
+}
+
+impl NamedDisplay for SystemCut {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SystemCut::Unidirectional(partition) => {
+                write!(f, "{} =/=> {}", format_elements(&partition.cut_from, names), format_elements(&partition.cut_to, names))
+            },
+            SystemCut::Bidirectional(partition) => {
+                write!(f, "{} <=/=> {}", format_elements(&partition.cut_from, names), format_elements(&partition.cut_to, names))
+            },
+            SystemCut::Directed(partition) => {
+                let parts: Vec<String> = partition.parts.iter().zip(partition.directions.iter()).map(|(part, direction)| {
+                    format!("{} {}", direction.marker(), format_elements(part, names))
+                }).collect();
+
+                write!(f, "{}", parts.join(", "))
+            },
+        }
+    }
+}
+
+impl NamedDisplay for MinimumInformationPartition {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+        self.partition.fmt_named(names, f)?;
+        write!(f, ", big phi: {}", self.phi)
+    }
+}
+
+fn format_core(core: &CoreRepertoire, mechanism: &BitBasis, names: &[String]) -> String {
+    // each part is written as mechanism/purview
+    let parts: Vec<String> = core.partition.parts.iter().map(|part| {
+        let part_mechanism = generate_indices(&mechanism.sub_basis(&part.mechanism).to_mask());
+        let part_purview = generate_indices(&core.purview.sub_basis(&part.purview).to_mask());
+
+        format!("{}/{}", format_elements(&part_mechanism, names), format_elements(&part_purview, names))
+    }).collect();
+
+    let purview = format_elements(&generate_indices(&core.purview.to_mask()), names);
+    format!("{} (phi: {}, MIP: {})", purview, core.phi, parts.join(" x "))
+}
+
+fn write_repertoire(label: &str, repertoire: &na::DVector<f64>, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "\n    {}:", label)?;
+    format_repertoire(repertoire, names).lines().try_for_each(|line| write!(f, "\n        {}", line))
+}
+
+// `{:#}` also writes the cause and effect repertoires of concepts
+impl NamedDisplay for Concept {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+        let mechanism = format_elements(&generate_indices(&self.mechanism.to_mask()), names);
+        write!(f, "{}: phi: {}, ", mechanism, self.phi)?;
+        write!(f, "cause: {}, ", format_core(&self.core_cause, &self.mechanism, names))?;
+        write!(f, "effect: {}", format_core(&self.core_effect, &self.mechanism, names))?;
+
+        if f.alternate() {
+            write_repertoire("CAUSE", &self.core_cause.repertoire, names, f)?;
+            write_repertoire("EFFECT", &self.core_effect.repertoire, names, f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl NamedDisplay for Constellation {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MIP: ")?;
+        self.mip.fmt_named(names, f)?;
+
+        self.mip.tied_partitions.iter().try_for_each(|partition| {
+            write!(f, "\nTied MIP: ")?;
+            partition.fmt_named(names, f)
+        })?;
+
+        self.concepts.iter().try_for_each(|concept| {
+            writeln!(f)?;
+            concept.fmt_named(names, f)
+        })
+    }
+}
+
+impl NamedDisplay for Complex {
+    fn fmt_named(&self, names: &[String], f: &mut fmt::Formatter) -> fmt::Result {
+        // the constellation is indexed within the complex
+        let sub_names: Vec<String> = self.elements.iter().map(|&i| names[i].clone()).collect();
+
+        writeln!(f, "Complex: {}", format_elements(&self.elements, names))?;
+        self.constellation.fmt_named(&sub_names, f)
+    }
+}
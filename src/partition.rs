@@ -1,54 +1,73 @@
-use crate::bitwise::USIZE_BASIS;
+use std::{collections::HashSet, fmt};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::bitwise::{BitSet, generate_mask};
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MechanismPart {
+    pub purview: Vec<usize>,
+    pub mechanism: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MechanismPartition {
-    pub left_purview: Vec<usize>,
-    pub left_mechanism: Vec<usize>,
-    pub right_purview: Vec<usize>,
-    pub right_mechanism: Vec<usize>,
+    pub parts: Vec<MechanismPart>, // the partitioned repertoire is the product of the parts
 }
 
 impl MechanismPartition {
     pub fn null_partition() -> MechanismPartition {
         MechanismPartition {
-            left_purview: Vec::<usize>::new(),
-            left_mechanism: Vec::<usize>::new(),
-            right_purview: Vec::<usize>::new(),
-            right_mechanism: Vec::<usize>::new(),
+            parts: Vec::<MechanismPart>::new(),
         }
     }
+
+    pub fn is_equivalent(&self, other: &MechanismPartition) -> bool {
+        // ignore the order of parts
+        let mut parts = self.parts.clone();
+        let mut other_parts = other.parts.clone();
+        parts.sort();
+        other_parts.sort();
+
+        parts == other_parts
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MechanismPartitionScheme {
+    Bipartition, // two parts of mechanism and purview, as IIT 3.0
+    All, // any number of parts, including a purview part without mechanism
+    Wedge, // two parts of mechanism, one of which can be empty, and a third purview part without mechanism, as PyPhi
 }
 
-pub struct MechanismPartitionIterator {
-    current: usize,
+pub struct MechanismPartitionIterator<S: BitSet = usize> {
+    current: S,
     purview_size: usize,
     mechanism_size: usize,
     mask_size: usize,
-    unused_mask: usize,
 }
 
-impl MechanismPartitionIterator {
-    pub fn construct(purview_size: usize, mechanism_size: usize) -> MechanismPartitionIterator {
+impl <S: BitSet> MechanismPartitionIterator<S> {
+    pub fn construct(purview_size: usize, mechanism_size: usize) -> MechanismPartitionIterator<S> {
         let mask_size = purview_size + mechanism_size;
 
-        let unused_mask = usize::MAX << (mask_size - 1);
-
         MechanismPartitionIterator {
-            current: 1, // 0 means no partition
-            purview_size: purview_size,
-            mechanism_size: mechanism_size,
-            mask_size: mask_size,
-            unused_mask: unused_mask,
+            current: S::singleton(0), // 0 means no partition
+            purview_size,
+            mechanism_size,
+            mask_size,
         }
     }
 }
 
-impl Iterator for MechanismPartitionIterator {
+impl <S: BitSet> Iterator for MechanismPartitionIterator<S> {
     type Item = MechanismPartition;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current & self.unused_mask != 0 {
+        if self.current.bit_len() >= self.mask_size {
             return None
         }
 
@@ -58,33 +77,34 @@ impl Iterator for MechanismPartitionIterator {
         let mut right_mechanism = Vec::<usize>::new();
 
         (0..self.mechanism_size).for_each(|i| {
-            if self.current & USIZE_BASIS[i] == 0 {
-                left_mechanism.push(i);
-            } else {
+            if self.current.contains(i) {
                 right_mechanism.push(i);
+            } else {
+                left_mechanism.push(i);
             }
         });
 
         (0..self.purview_size).zip(self.mechanism_size..self.mask_size).for_each(|(i, j)| {
-            if self.current & USIZE_BASIS[j] == 0 {
-                left_purview.push(i);
-            } else {
+            if self.current.contains(j) {
                 right_purview.push(i);
+            } else {
+                left_purview.push(i);
             }
         });
 
-        self.current += 1;
+        self.current.increment();
 
         Some(MechanismPartition {
-            left_purview: left_purview,
-            left_mechanism: left_mechanism,
-            right_purview: right_purview,
-            right_mechanism: right_mechanism,
+            parts: vec![
+                MechanismPart { purview: left_purview, mechanism: left_mechanism },
+                MechanismPart { purview: right_purview, mechanism: right_mechanism },
+            ],
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SystemPartition {
     pub cut_from: Vec<usize>,
     pub cut_to: Vec<usize>,
@@ -99,29 +119,29 @@ impl SystemPartition {
     }
 }
 
-pub struct SystemPartitionIterator {
-    current: usize,
+pub struct SystemPartitionIterator<S: BitSet = usize> {
+    current: S,
     mask_size: usize,
-    stop: usize,
+    stop: S,
 }
 
-impl SystemPartitionIterator {
-    pub fn construct(system_size: usize) -> SystemPartitionIterator {
-        let stop = !(usize::MAX << system_size);
+impl <S: BitSet> SystemPartitionIterator<S> {
+    pub fn construct(system_size: usize) -> SystemPartitionIterator<S> {
+        let indices: Vec<usize> = (0..system_size).collect();
 
         SystemPartitionIterator {
-            current: 1,
+            current: S::singleton(0),
             mask_size: system_size,
-            stop: stop,
+            stop: generate_mask(&indices),
         }
     }
 }
 
-impl Iterator for SystemPartitionIterator {
+impl <S: BitSet> Iterator for SystemPartitionIterator<S> {
     type Item = SystemPartition;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current >= self.stop {
+        if self.current == self.stop || self.current.bit_len() > self.mask_size {
             return None
         }
 
@@ -129,18 +149,273 @@ impl Iterator for SystemPartitionIterator {
         let mut cut_to = Vec::<usize>::new();
 
         (0..self.mask_size).for_each(|i| {
-            if self.current & USIZE_BASIS[i] == 0 {
-                cut_from.push(i);
-            } else {
+            if self.current.contains(i) {
                 cut_to.push(i);
+            } else {
+                cut_from.push(i);
             }
         });
 
-        self.current += 1;
+        self.current.increment();
 
         Some(SystemPartition {
-            cut_from: cut_from,
-            cut_to: cut_to,
+            cut_from,
+            cut_to,
         })
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SystemPartitionScheme {
+    Unidirectional, // cut_from =/=> cut_to, as IIT 3.0
+    Bidirectional, // cut_from <=/=> cut_to
+    Directed, // directional partitions of IIT 4.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CutDirection {
+    Input,
+    Output,
+    Bidirectional,
+}
+
+impl CutDirection {
+    pub fn marker(&self) -> &'static str {
+        match self {
+            CutDirection::Input => "=/=>",
+            CutDirection::Output => "<=/=",
+            CutDirection::Bidirectional => "<=/=>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DirectedPartition {
+    pub parts: Vec<Vec<usize>>,
+    pub directions: Vec<CutDirection>, // which connections of each part are cut
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SystemCut {
+    Unidirectional(SystemPartition),
+    Bidirectional(SystemPartition),
+    Directed(DirectedPartition),
+}
+
+impl SystemCut {
+    pub fn null_cut() -> SystemCut {
+        SystemCut::Unidirectional(SystemPartition::null_partition())
+    }
+
+    // return masks of the elements whose connections to each element are cut
+    pub fn severed_inputs(&self, system_size: usize) -> Vec<usize> {
+        let mut severed = vec![0; system_size];
+
+        let mut sever = |sources: &[usize], targets: &[usize]| {
+            let source_mask: usize = generate_mask(sources);
+            targets.iter().for_each(|&target| severed[target] |= source_mask);
+        };
+
+        match self {
+            SystemCut::Unidirectional(partition) => {
+                sever(&partition.cut_from, &partition.cut_to);
+            },
+            SystemCut::Bidirectional(partition) => {
+                sever(&partition.cut_from, &partition.cut_to);
+                sever(&partition.cut_to, &partition.cut_from);
+            },
+            SystemCut::Directed(partition) => {
+                partition.parts.iter().zip(partition.directions.iter()).for_each(|(part, direction)| {
+                    let others: Vec<usize> = (0..system_size).filter(|i| !part.contains(i)).collect();
+
+                    if *direction != CutDirection::Output {
+                        sever(&others, part);
+                    }
+
+                    if *direction != CutDirection::Input {
+                        sever(part, &others);
+                    }
+                });
+            },
+        }
+
+        severed
+    }
+}
+
+impl fmt::Display for SystemCut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SystemCut::Unidirectional(partition) => write!(f, "{:?} =/=> {:?}", partition.cut_from, partition.cut_to),
+            SystemCut::Bidirectional(partition) => write!(f, "{:?} <=/=> {:?}", partition.cut_from, partition.cut_to),
+            SystemCut::Directed(partition) => {
+                let parts: Vec<String> = partition.parts.iter().zip(partition.directions.iter()).map(|(part, direction)| {
+                    format!("{} {:?}", direction.marker(), part)
+                }).collect();
+
+                write!(f, "{}", parts.join(", "))
+            },
+        }
+    }
+}
+
+fn generate_set_partitions(system_size: usize) -> Vec<Vec<Vec<usize>>> {
+    // enumerate restricted growth strings
+    let mut partitions = Vec::<Vec<Vec<usize>>>::new();
+    let mut labels = vec![0; system_size];
+
+    loop {
+        let size = labels.iter().max().map_or(0, |&x| x + 1);
+        let mut parts = vec![Vec::<usize>::new(); size];
+        labels.iter().enumerate().for_each(|(i, &label)| parts[label].push(i));
+        partitions.push(parts);
+
+        // find the rightmost label which can be incremented
+        let next = (1..system_size).rev().find(|&i| labels[i] <= *labels[..i].iter().max().unwrap());
+        match next {
+            Some(i) => {
+                labels[i] += 1;
+                labels[i + 1..].iter_mut().for_each(|x| *x = 0);
+            },
+            None => return partitions,
+        }
+    }
+}
+
+pub fn generate_directed_partitions(system_size: usize) -> Vec<DirectedPartition> {
+    const DIRECTIONS: [CutDirection; 3] = [CutDirection::Input, CutDirection::Output, CutDirection::Bidirectional];
+
+    let mut found = HashSet::<Vec<usize>>::new();
+    let mut partitions = Vec::<DirectedPartition>::new();
+
+    generate_set_partitions(system_size).into_iter().filter(|parts| parts.len() > 1).for_each(|parts| {
+        let combination_size = DIRECTIONS.len().pow(parts.len() as u32);
+
+        (0..combination_size).for_each(|combination| {
+            let directions: Vec<CutDirection> = (0..parts.len()).map(|i| {
+                DIRECTIONS[combination / DIRECTIONS.len().pow(i as u32) % DIRECTIONS.len()]
+            }).collect();
+
+            let partition = DirectedPartition { parts: parts.clone(), directions };
+
+            // different partitions can sever the same connections
+            let severed = SystemCut::Directed(partition.clone()).severed_inputs(system_size);
+            if found.insert(severed) {
+                partitions.push(partition);
+            }
+        });
+    });
+
+    partitions
+}
+
+pub fn generate_system_cuts(scheme: SystemPartitionScheme, system_size: usize) -> Box<dyn Iterator<Item = SystemCut> + Send> {
+    let partitions = SystemPartitionIterator::<usize>::construct(system_size);
+
+    match scheme {
+        SystemPartitionScheme::Unidirectional => Box::new(partitions.map(SystemCut::Unidirectional)),
+        SystemPartitionScheme::Bidirectional => {
+            // the reversed one is the same cut
+            Box::new(partitions.filter(|p| p.cut_from.contains(&0)).map(SystemCut::Bidirectional))
+        },
+        SystemPartitionScheme::Directed => {
+            Box::new(generate_directed_partitions(system_size).into_iter().map(SystemCut::Directed))
+        },
+    }
+}
+
+fn generate_mechanism_partitions_with(mechanism_parts: Vec<Vec<usize>>, purview_size: usize, partitions: &mut Vec<MechanismPartition>) {
+    // assign each purview element to one of the mechanism parts or to the last part without mechanism
+    let mechanism_size: usize = mechanism_parts.iter().map(|part| part.len()).sum();
+    let part_size = mechanism_parts.len() + 1;
+    let combination_size = part_size.pow(purview_size as u32);
+
+    (0..combination_size).for_each(|combination| {
+        let mut parts: Vec<MechanismPart> = mechanism_parts.iter().map(|mechanism| {
+            MechanismPart { purview: Vec::<usize>::new(), mechanism: mechanism.clone() }
+        }).collect();
+        parts.push(MechanismPart { purview: Vec::<usize>::new(), mechanism: Vec::<usize>::new() });
+
+        (0..purview_size).for_each(|i| {
+            parts[combination / part_size.pow(i as u32) % part_size].purview.push(i);
+        });
+
+        parts.retain(|part| !part.purview.is_empty() || !part.mechanism.is_empty());
+
+        // mechanism must be partitioned unless the purview is fully cut away from it, as PyPhi
+        let is_unpartitioned = parts.iter().any(|part| part.mechanism.len() == mechanism_size && !part.purview.is_empty());
+
+        if parts.len() > 1 && !is_unpartitioned {
+            partitions.push(MechanismPartition { parts });
+        }
+    });
+}
+
+fn generate_wedge_partitions(purview_size: usize, mechanism_size: usize) -> Vec<MechanismPartition> {
+    // a bipartition of mechanism, whose first part can be empty, times a directed tripartition of purview
+    let mut found = HashSet::<Vec<MechanismPart>>::new();
+    let mut partitions = Vec::<MechanismPartition>::new();
+
+    let numerator_size = 1 << mechanism_size.saturating_sub(1);
+    let denominator_size = 3usize.pow(purview_size as u32);
+
+    (0..numerator_size).for_each(|numerator| {
+        let (mechanism0, mechanism1): (Vec<usize>, Vec<usize>) = (0..mechanism_size).partition(|&i| (numerator >> i) & 1 == 1);
+
+        (0..denominator_size).for_each(|denominator| {
+            let mut purviews = vec![Vec::<usize>::new(); 3];
+            (0..purview_size).for_each(|i| purviews[denominator / 3usize.pow(i as u32) % 3].push(i));
+
+            let is_valid = (!mechanism0.is_empty() || !purviews[0].is_empty())
+                && (!mechanism1.is_empty() || !purviews[1].is_empty())
+                && ((!mechanism0.is_empty() && !mechanism1.is_empty()) || purviews[0].is_empty() || purviews[1].is_empty());
+            if !is_valid {
+                return;
+            }
+
+            let mut parts = vec![
+                MechanismPart { purview: purviews[0].clone(), mechanism: mechanism0.clone() },
+                MechanismPart { purview: purviews[1].clone(), mechanism: mechanism1.clone() },
+                MechanismPart { purview: purviews[2].clone(), mechanism: Vec::<usize>::new() },
+            ];
+            parts.retain(|part| !part.purview.is_empty() || !part.mechanism.is_empty());
+
+            // two parts both without mechanism or both without purview can be merged into one
+            let is_compressible = (0..parts.len()).any(|i| (i + 1..parts.len()).any(|j| {
+                (parts[i].mechanism.is_empty() && parts[j].mechanism.is_empty()) || (parts[i].purview.is_empty() && parts[j].purview.is_empty())
+            }));
+
+            parts.sort();
+            if !is_compressible && found.insert(parts.clone()) {
+                partitions.push(MechanismPartition { parts });
+            }
+        });
+    });
+
+    partitions
+}
+
+pub fn generate_mechanism_partitions(scheme: MechanismPartitionScheme, purview_size: usize, mechanism_size: usize) -> Box<dyn Iterator<Item = MechanismPartition>> {
+    let set_partitions = generate_set_partitions(mechanism_size).into_iter();
+
+    let mechanism_partitions: Vec<Vec<Vec<usize>>> = match scheme {
+        MechanismPartitionScheme::Bipartition => {
+            return Box::new(MechanismPartitionIterator::<usize>::construct(purview_size, mechanism_size));
+        },
+        MechanismPartitionScheme::All => set_partitions.collect(),
+        MechanismPartitionScheme::Wedge => {
+            return Box::new(generate_wedge_partitions(purview_size, mechanism_size).into_iter());
+        },
+    };
+
+    let mut partitions = Vec::<MechanismPartition>::new();
+    mechanism_partitions.into_iter().for_each(|mechanism_parts| {
+        generate_mechanism_partitions_with(mechanism_parts, purview_size, &mut partitions);
+    });
+
+    Box::new(partitions.into_iter())
+}
@@ -0,0 +1,63 @@
+use std::{fmt, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Duration};
+
+
+#[derive(Debug, Clone)]
+pub struct Progress<'a> {
+    pub candidate: &'a [usize], // elements of the candidate just searched
+    pub phi: f64,
+    pub current_count: usize,
+    pub total_count: usize,
+    pub elapsed: Duration, // for the candidate
+    pub total_elapsed: Duration, // since the search started
+}
+
+impl <'a> fmt::Display for Progress<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = format!("PROGRESS={}/{}", self.current_count, self.total_count);
+        let candidate = format!("CANDIDATE={:?}", self.candidate);
+        let phi = format!("BIG_PHI={}", self.phi);
+        let time = format!("TIME={:.2e}", self.elapsed.as_secs_f64());
+
+        write!(f, "{}, {}, {}, {}", count, candidate, phi, time)
+    }
+}
+
+pub trait ProgressObserver {
+    // called after each candidate of complex search
+    fn notify(&self, progress: &Progress);
+}
+
+// same as the log of `search_complex`
+pub struct LogObserver;
+
+impl ProgressObserver for LogObserver {
+    fn notify(&self, progress: &Progress) {
+        println!("{}", progress);
+    }
+}
+
+pub struct SilentObserver;
+
+impl ProgressObserver for SilentObserver {
+    fn notify(&self, _progress: &Progress) {}
+}
+
+// shared between clones, so that another thread can cancel a running search
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
@@ -1,6 +1,5 @@
-use std::usize;
 use nalgebra as na;
-use crate::basis::BitBasis;
+use crate::{basis::BitBasis, bitwise::{BitSet, generate_indices}, tpm::{LinkModel, TransitionModel}};
 
 
 pub fn normalize_repertoire(repertoire: &mut na::DVector<f64>, sum: Option<f64>) {
@@ -12,20 +11,20 @@ pub fn normalize_repertoire(repertoire: &mut na::DVector<f64>, sum: Option<f64>)
     repertoire.apply(|x| x * norm_term);
 }
 
-fn calc_unconstrained_cause(purview: &BitBasis, tpm: &na::DMatrix<f64>) -> na::DVector<f64> {
-    let ndim = tpm.nrows();
+fn calc_unconstrained_cause<T: TransitionModel + ?Sized>(purview: &BitBasis, tpm: &T) -> na::DVector<f64> {
+    let ndim = tpm.state_size();
     let p = 1.0 / purview.image_size() as f64;
     na::DVector::<f64>::from_element(ndim, p)
 }
 
-fn calc_elementary_cause(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &na::DMatrix<f64>) -> na::DVector<f64> {
-    let ndim = tpm.nrows();
+fn calc_elementary_cause<T: TransitionModel + ?Sized>(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &T) -> na::DVector<f64> {
+    let ndim = tpm.state_size();
 
     let c_mechanism = mechanism.generate_complement_basis();
 
     let mut accumulated = na::DVector::<f64>::zeros(ndim);
-    c_mechanism.span(mechanism.fixed_state(current_state)).for_each(|col| {
-        accumulated += tpm.column(col);
+    c_mechanism.span(mechanism.fixed_state(&current_state)).for_each(|col| {
+        accumulated.iter_mut().enumerate().for_each(|(row, x)| *x += tpm.transition(row, col));
     });
 
 
@@ -56,9 +55,9 @@ fn calc_elementary_cause(purview: &BitBasis, mechanism: &BitBasis, current_state
     marginal
 }
 
-pub fn calc_cause_repertoire(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &na::DMatrix<f64>) -> na::DVector<f64> {
+pub fn calc_cause_repertoire<T: TransitionModel + ?Sized>(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &T) -> na::DVector<f64> {
     if purview.dim == 0 {
-        return na::DVector::<f64>::from_element(tpm.nrows(), 1.0);
+        return na::DVector::<f64>::from_element(tpm.state_size(), 1.0);
     }
 
     match mechanism.dim {
@@ -77,13 +76,13 @@ pub fn calc_cause_repertoire(purview: &BitBasis, mechanism: &BitBasis, current_s
     }
 }
 
-fn calc_elementary_effect(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &na::DMatrix<f64>) -> na::DVector<f64> {
-    let ndim = tpm.nrows();
+fn calc_elementary_effect<T: TransitionModel + ?Sized>(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &T) -> na::DVector<f64> {
+    let ndim = tpm.state_size();
 
-    let mut accumulated = na::DVector::<f64>::zeros(ndim).transpose();
+    let mut accumulated = na::DVector::<f64>::zeros(ndim);
     let c_mechanism = mechanism.generate_complement_basis();
-    c_mechanism.span(mechanism.fixed_state(current_state)).for_each(|row| {
-        accumulated += tpm.row(row);
+    c_mechanism.span(mechanism.fixed_state(&current_state)).for_each(|row| {
+        accumulated.iter_mut().enumerate().for_each(|(col, x)| *x += tpm.transition(row, col));
     });
 
 
@@ -106,9 +105,9 @@ fn calc_elementary_effect(purview: &BitBasis, mechanism: &BitBasis, current_stat
     marginal
 }
 
-pub fn calc_effect_repertoire(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &na::DMatrix<f64>) -> na::DVector<f64> {
+pub fn calc_effect_repertoire<T: TransitionModel + ?Sized>(purview: &BitBasis, mechanism: &BitBasis, current_state: usize, tpm: &T) -> na::DVector<f64> {
     if purview.dim == 0 {
-        return na::DVector::<f64>::from_element(tpm.nrows(), 1.0);
+        return na::DVector::<f64>::from_element(tpm.state_size(), 1.0);
     }
 
     if purview.dim == 1 {
@@ -124,3 +123,57 @@ pub fn calc_effect_repertoire(purview: &BitBasis, mechanism: &BitBasis, current_
         joint
     }
 }
+
+fn generate_elements<S: BitSet>(basis: &BitBasis<S>) -> Vec<usize> {
+    // in the order of vectors, which is the order of bits in `compress`
+    basis.vectors.iter().flat_map(generate_indices).collect()
+}
+
+fn calc_joint_repertoire(probs: &[f64]) -> na::DVector<f64> {
+    // elements are independent, the i-th one is ON with `probs[i]`
+    na::DVector::<f64>::from_fn(1 << probs.len(), |state, _| {
+        probs.iter().enumerate().fold(1.0, |acc, (i, &p)| {
+            if state & (1 << i) == 0 {
+                acc * (1.0 - p)
+            } else {
+                acc * p
+            }
+        })
+    })
+}
+
+// unlike `calc_cause_repertoire`, only the states of `purview` are given, in the order of `purview.compress`
+pub fn calc_link_cause_repertoire<S: BitSet>(purview: &BitBasis<S>, mechanism: &BitBasis<S>, current_state: &S, model: &LinkModel) -> na::DVector<f64> {
+    if purview.dim == 0 {
+        return na::DVector::<f64>::from_element(1, 1.0);
+    }
+
+    let purview_mask = purview.to_mask();
+    let mut joint = na::DVector::<f64>::from_element(purview.image_size(), 1.0);
+
+    generate_elements(mechanism).iter().for_each(|&element| {
+        let is_on = current_state.contains(element);
+
+        let mut elementary = na::DVector::<f64>::from_iterator(purview.image_size(), purview.span(S::empty()).map(|purview_state| {
+            let p = model.calc_averaged_probability(element, &purview_state, &purview_mask);
+            if is_on { p } else { 1.0 - p }
+        }));
+
+        normalize_repertoire(&mut elementary, None);
+        joint.component_mul_assign(&elementary);
+    });
+
+    normalize_repertoire(&mut joint, None);
+    joint
+}
+
+// same as above for `calc_effect_repertoire`
+pub fn calc_link_effect_repertoire<S: BitSet>(purview: &BitBasis<S>, mechanism: &BitBasis<S>, current_state: &S, model: &LinkModel) -> na::DVector<f64> {
+    let mechanism_mask = mechanism.to_mask();
+
+    let probs: Vec<f64> = generate_elements(purview).iter().map(|&element| {
+        model.calc_averaged_probability(element, current_state, &mechanism_mask)
+    }).collect();
+
+    calc_joint_repertoire(&probs)
+}
@@ -0,0 +1,21 @@
+use serde::{Serialize, de::DeserializeOwned};
+use crate::error::{PhiError, Result};
+
+
+// JSON for later comparison or plotting, and bincode for compact storage
+
+pub fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| PhiError::Serialization(e.to_string()))
+}
+
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T> {
+    serde_json::from_str(json).map_err(|e| PhiError::Serialization(e.to_string()))
+}
+
+pub fn to_binary<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    bincode::serialize(value).map_err(|e| PhiError::Serialization(e.to_string()))
+}
+
+pub fn from_binary<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    bincode::deserialize(bytes).map_err(|e| PhiError::Serialization(e.to_string()))
+}
@@ -1,58 +1,162 @@
-use std::{fs::File, io::{BufRead, BufReader}, str::FromStr};
-use strum_macros::EnumString;
+use std::{fs::File, io::{BufRead, BufReader, Write}, str::FromStr};
+use strum_macros::{Display, EnumString};
+use crate::{error::{PhiError, Result}, tokenize::tokenize_line};
 
 
-#[derive(Debug, EnumString)]
+const PARAMETER_SEPARATOR: char = ':';
+
+#[derive(Debug, Clone, Copy, PartialEq, Display, EnumString)]
 pub enum LinkType {
     COPY,
     NOT,
     OR,
     AND,
     XOR,
+    NAND,
+    NOR,
+    XNOR,
+    MAJ,
+    MIN,
     ANY,
     ALL,
     EVEN,
     ODD,
     NOISY,
+    THRESHOLD,
+    SIGMOID,
+    TABLE,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LinkInfo {
     pub element: String,
     pub link_type: LinkType,
+    pub parameters: Vec<f64>, // given as `LINK:p1:p2`
     pub condition: Vec<String>,
+    pub weights: Vec<f64>, // given as `ELEMENT:w`, 1.0 if omitted
+}
+
+fn sif_error(line: usize, column: usize, message: String) -> PhiError {
+    PhiError::Sif { file: None, line, column, message }
+}
+
+fn end_column(token: &(usize, &str)) -> usize {
+    token.0 + token.1.chars().count()
 }
 
-fn parse_sif_line(line: &str) -> LinkInfo {
-    let mut iter = line.split(" ");
+fn parse_sif_line(line: &str, line_number: usize) -> Result<Option<LinkInfo>> {
+    let tokens = tokenize_line(line, char::is_whitespace);
+
+    let element = match tokens.first() {
+        Some(token) => token,
+        None => return Ok(None), // blank or comment line
+    };
 
-    let element = iter.next().expect("No element is defined");
+    let link_token = tokens.get(1).ok_or_else(|| {
+        sif_error(line_number, end_column(element), "No link is defined".to_string())
+    })?;
 
-    let link_type_str = iter.next().expect("No link is defined");
+    let mut link_parts = link_token.1.split(PARAMETER_SEPARATOR);
+    let link_type_str = link_parts.next().unwrap();
+    let link_type = LinkType::from_str(link_type_str).map_err(|_| {
+        sif_error(line_number, link_token.0, format!("Unknown link type '{}'", link_type_str))
+    })?;
 
-    let condition: Vec<String> = iter.map(|x| x.to_string()).collect();
+    let mut parameters = Vec::<f64>::new();
+    let mut column = link_token.0 + link_type_str.chars().count() + 1;
+    for part in link_parts {
+        parameters.push(parse_number(part, line_number, column)?);
+        column += part.chars().count() + 1;
+    }
 
-    if condition.len() == 0 {
-        panic!("No condition is defined")
+    let mut condition = Vec::<String>::new();
+    let mut weights = Vec::<f64>::new();
+    for (column, token) in tokens[2..].iter() {
+        match token.split_once(PARAMETER_SEPARATOR) {
+            Some((name, weight)) => {
+                condition.push(name.to_string());
+                weights.push(parse_number(weight, line_number, column + name.chars().count() + 1)?);
+            },
+            None => {
+                condition.push(token.to_string());
+                weights.push(1.0);
+            },
+        }
     }
 
-    LinkInfo {
-        element: element.to_string(),
-        condition: condition,
-        link_type: LinkType::from_str(link_type_str).unwrap(),
+    if condition.is_empty() {
+        return Err(sif_error(line_number, end_column(link_token), "No condition is defined".to_string()));
     }
+
+    Ok(Some(LinkInfo {
+        element: element.1.to_string(),
+        link_type,
+        parameters,
+        condition,
+        weights,
+    }))
 }
 
-pub fn read_sif(path: &str) -> Vec<LinkInfo> {
-    let file = File::open(path).unwrap();
+fn parse_number(text: &str, line_number: usize, column: usize) -> Result<f64> {
+    f64::from_str(text).map_err(|_| {
+        sif_error(line_number, column, format!("Invalid number '{}'", text))
+    })
+}
 
+pub fn read_sif_from<R: BufRead>(reader: R) -> Result<Vec<LinkInfo>> {
     let mut infos = Vec::<LinkInfo>::new();
 
-    for line in BufReader::new(file).lines() {
-        let unwrapped = line.unwrap();
-        let parsed = parse_sif_line(&unwrapped);
-        infos.push(parsed);
+    for (i, line) in reader.lines().enumerate() {
+        let unwrapped = line?;
+
+        if let Some(info) = parse_sif_line(&unwrapped, i + 1)? {
+            infos.push(info);
+        }
+    }
+
+    Ok(infos)
+}
+
+pub fn parse_sif_str(sif: &str) -> Result<Vec<LinkInfo>> {
+    read_sif_from(sif.as_bytes())
+}
+
+pub fn try_read_sif(path: &str) -> Result<Vec<LinkInfo>> {
+    let file = File::open(path)?;
+
+    read_sif_from(BufReader::new(file)).map_err(|e| e.with_file(path))
+}
+
+pub fn read_sif(path: &str) -> Vec<LinkInfo> {
+    try_read_sif(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn format_link(info: &LinkInfo) -> String {
+    let mut link = info.link_type.to_string();
+
+    info.parameters.iter().for_each(|p| {
+        link += &format!("{}{}", PARAMETER_SEPARATOR, p);
+    });
+
+    link
+}
+
+fn format_condition(info: &LinkInfo) -> String {
+    let conditions: Vec<String> = info.condition.iter().zip(info.weights.iter()).map(|(c, &w)| {
+        if w == 1.0 {
+            c.clone()
+        } else {
+            format!("{}{}{}", c, PARAMETER_SEPARATOR, w)
+        }
+    }).collect();
+
+    conditions.join(" ")
+}
+
+pub fn write_sif<W: Write>(writer: &mut W, infos: &[LinkInfo]) -> Result<()> {
+    for info in infos.iter() {
+        writeln!(writer, "{} {} {}", info.element, format_link(info), format_condition(info))?;
     }
 
-    infos
+    Ok(())
 }
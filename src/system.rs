@@ -1,34 +1,39 @@
-use std::{sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::SystemTime};
+use std::{iter::Enumerate, sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::Instant};
 
 use nalgebra as na;
-use crate::{basis::BitBasis, bitwise::USIZE_BASIS, compare::{Comparison, compare_roughly}, emd::calc_constellation_emd, mechanism::{Concept, CoreRepertoire, construct_vector_from_row, generate_all_repertoire_parts, search_concept_with_parts}, partition::{MechanismPartition, SystemPartition, SystemPartitionIterator}, tpm::{calc_fixed_marginal_tpm, calc_partitioned_marginal_tpm}};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::{basis::BitBasis, bitwise::USIZE_BASIS, compare::{Comparison, compare_within, is_almost_equal}, config::AnalysisConfig, emd::try_calc_constellation_distance, error::{PhiError, Result}, mechanism::{Concept, CoreRepertoire, construct_vector_from_row, generate_all_repertoire_parts, try_search_concept_with_parts}, partition::{MechanismPartition, SystemCut, generate_system_cuts}, progress::{CancellationToken, LogObserver, Progress, ProgressObserver, SilentObserver}, tpm::{calc_background_marginal_tpm, calc_cut_marginal_tpm}};
 
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MinimumInformationPartition {
-    pub partition: SystemPartition,
+    pub partition: SystemCut,
     pub phi: f64,
+    pub tied_partitions: Vec<SystemCut>, // filled only when ties are reported, including `partition`
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Constellation {
     pub concepts: Vec<Concept>,
     pub null_concept: Concept,
     pub mip: MinimumInformationPartition,
 }
 
-pub fn search_constellation_with_parts(cause_parts: &na::DMatrix<f64>, effect_parts: &na::DMatrix<f64>) -> Constellation {
-    let system_basis = BitBasis::construct_from_max_image_size(cause_parts.ncols());
+pub fn try_search_constellation_with_parts(cause_parts: &na::DMatrix<f64>, effect_parts: &na::DMatrix<f64>, config: &AnalysisConfig) -> Result<Constellation> {
+    let system_basis: BitBasis = BitBasis::construct_from_max_image_size(cause_parts.ncols());
     let mut concepts = Vec::<Concept>::new();
 
-    (1..system_basis.max_image_size()).for_each(|mask| {
+    for mask in 1..system_basis.max_image_size() {
         let mechanism = BitBasis::construct_from_mask(mask, system_basis.max_dim);
 
-        let concept = search_concept_with_parts(&mechanism, cause_parts, effect_parts);
-        if concept.phi > 0.0 {
+        let concept = try_search_concept_with_parts(&mechanism, cause_parts, effect_parts, config)?;
+        if !is_almost_equal(concept.phi, 0.0, config.tolerance) {
             concepts.push(concept);
         };
-    });
+    };
 
     let unconstrained_mask = system_basis.to_mask() << system_basis.max_dim;
     let unconstrained_cause = construct_vector_from_row(unconstrained_mask, cause_parts);
@@ -41,149 +46,216 @@ pub fn search_constellation_with_parts(cause_parts: &na::DMatrix<f64>, effect_pa
             repertoire: unconstrained_cause,
             partition: MechanismPartition::null_partition(),
             phi: 0.0,
+            tied_purviews: Vec::<BitBasis>::new(),
+            tied_partitions: Vec::<MechanismPartition>::new(),
         },
         core_effect: CoreRepertoire {
             purview: BitBasis::null_basis(system_basis.max_dim),
             repertoire: unconstrained_effect,
             partition: MechanismPartition::null_partition(),
             phi: 0.0,
+            tied_purviews: Vec::<BitBasis>::new(),
+            tied_partitions: Vec::<MechanismPartition>::new(),
         },
         phi: 0.0,
     };
 
-    Constellation {
-        concepts: concepts,
-        null_concept: null_concept,
+    Ok(Constellation {
+        concepts,
+        null_concept,
         mip: MinimumInformationPartition {
-            partition: SystemPartition::null_partition(),
+            partition: SystemCut::null_cut(),
             phi: 0.0,
+            tied_partitions: Vec::<SystemCut>::new(),
         },
-    }
+    })
 }
 
-fn get_assigned_partition(partitions: &Arc<Mutex<SystemPartitionIterator>>) -> Option<SystemPartition> {
+pub fn search_constellation_with_parts(cause_parts: &na::DMatrix<f64>, effect_parts: &na::DMatrix<f64>, config: &AnalysisConfig) -> Constellation {
+    try_search_constellation_with_parts(cause_parts, effect_parts, config).unwrap_or_else(|e| panic!("{}", e))
+}
+
+type SharedCuts = Arc<Mutex<Enumerate<Box<dyn Iterator<Item = SystemCut> + Send>>>>;
+
+fn get_assigned_partition(partitions: &SharedCuts) -> Option<(usize, SystemCut)> {
     partitions.lock().unwrap().next()
 }
 
-fn challenge_update(emd: f64, partition: SystemPartition, mip: &Arc<Mutex<MinimumInformationPartition>>) -> bool {
+#[derive(Debug)]
+struct MipSearch {
+    min_emd: f64,
+    candidates: Vec<(usize, f64, SystemCut)>, // cuts with their order which were almost equal to or better than MIP when found
+}
+
+fn challenge_update(index: usize, emd: f64, partition: SystemCut, search: &Arc<Mutex<MipSearch>>, config: &AnalysisConfig) -> bool {
+    let tolerance = config.tolerance;
     // return false if MIP can fully reduce the system
-    let mut locked = mip.lock().unwrap();
+    let mut locked = search.lock().unwrap();
+
+    if emd < locked.min_emd || is_almost_equal(emd, locked.min_emd, tolerance) {
+        if emd < locked.min_emd {
+            locked.min_emd = emd;
+            locked.candidates.retain(|(_, other, _)| is_almost_equal(*other, emd, tolerance));
+        }
 
-    if emd < locked.phi {
-        locked.partition = partition;
-        locked.phi = emd;
+        locked.candidates.push((index, emd, partition));
     };
 
-    if let Comparison::AlmostEqual = compare_roughly(locked.phi, 0.0) {
-        locked.phi = 0.0;
-        false
-    } else {
-        true
+    // all cuts are needed to find ties, otherwise the following cuts cannot precede the found one
+    config.report_ties || !is_almost_equal(locked.min_emd, 0.0, tolerance)
+}
+
+fn select_mip(search: MipSearch, config: &AnalysisConfig) -> MinimumInformationPartition {
+    let tolerance = config.tolerance;
+    // break ties by the order of cuts, so that the result does not depend on the number of threads
+    let MipSearch { min_emd, mut candidates } = search;
+    candidates.retain(|(_, emd, _)| is_almost_equal(*emd, min_emd, tolerance));
+    candidates.sort_by_key(|(index, _, _)| *index);
+
+    let mut mip = match candidates.first() {
+        Some((_, emd, partition)) => MinimumInformationPartition {
+            partition: partition.clone(),
+            phi: *emd,
+            tied_partitions: Vec::<SystemCut>::new(),
+        },
+        None => MinimumInformationPartition { // no possible partition found
+            partition: SystemCut::null_cut(),
+            phi: 0.0,
+            tied_partitions: Vec::<SystemCut>::new(),
+        },
+    };
+
+    if is_almost_equal(mip.phi, 0.0, tolerance) {
+        mip.phi = 0.0;
     }
+
+    if config.report_ties {
+        mip.tied_partitions = candidates.into_iter().map(|(_, _, partition)| partition).collect();
+    }
+
+    mip
 }
 
-pub fn search_constellation_with_mip(current_state: usize, tpm: &Arc<na::DMatrix<f64>>, num_threads: usize) -> Constellation {
-    let system_basis = BitBasis::construct_from_max_image_size(tpm.ncols());
+pub fn try_search_constellation_with_mip(current_state: usize, tpm: &Arc<na::DMatrix<f64>>, config: &AnalysisConfig, cancel: &CancellationToken) -> Result<Constellation> {
+    config.validate()?;
 
-    let cause_parts = Arc::new(generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &tpm));
-    let effect_parts = Arc::new(generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &tpm));
-    let criterion = Arc::new(search_constellation_with_parts(&cause_parts, &effect_parts));
+    let system_basis: BitBasis = BitBasis::construct_from_max_image_size(tpm.ncols());
 
-    let mip = Arc::new(Mutex::new(MinimumInformationPartition {
-        partition: SystemPartition::null_partition(),
-        phi: f64::INFINITY,
+    let cause_parts = Arc::new(generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, tpm.as_ref()));
+    let effect_parts = Arc::new(generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, tpm.as_ref()));
+    let criterion = Arc::new(try_search_constellation_with_parts(&cause_parts, &effect_parts, config)?);
+
+    let search = Arc::new(Mutex::new(MipSearch {
+        min_emd: f64::INFINITY,
+        candidates: Vec::<(usize, f64, SystemCut)>::new(),
     }));
 
-    let partitions = Arc::new(Mutex::new(SystemPartitionIterator::construct(system_basis.max_dim)));
+    let partitions: SharedCuts = Arc::new(Mutex::new(generate_system_cuts(config.system_scheme, system_basis.max_dim).enumerate()));
 
-    let mut handles = Vec::<JoinHandle<()>>::new();
+    let mut handles = Vec::<JoinHandle<Result<()>>>::new();
 
-    (0..num_threads).for_each(|_| {
+    (0..config.num_threads).for_each(|_| {
         let cloned_tpm = tpm.clone();
         let cloned_criterion = criterion.clone();
-        let cloned_mip = mip.clone();
+        let cloned_search = search.clone();
         let cloned_partitions = partitions.clone();
+        let cloned_cancel = cancel.clone();
+        let config = config.clone();
+        let partitioned_config = AnalysisConfig { report_ties: false, ..config.clone() };
 
         let handle = thread::spawn(move || {
-            loop {
-                if let Some(partition) = get_assigned_partition(&cloned_partitions) {
-                    let partitioned_tpm = calc_partitioned_marginal_tpm(&partition, &cloned_tpm);
-                    let partitioned_cause_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &partitioned_tpm);
-                    let partitioned_effect_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &partitioned_tpm);
-                    let partitioned = search_constellation_with_parts(&partitioned_cause_parts, &partitioned_effect_parts);
-
-                    let emd = calc_constellation_emd(&cloned_criterion, &partitioned);
-
-                    if !challenge_update(emd, partition, &cloned_mip) {
-                        break;
-                    };
-                } else {
+            while let Some((index, partition)) = get_assigned_partition(&cloned_partitions) {
+                if cloned_cancel.is_cancelled() {
+                    break;
+                };
+
+                let partitioned_tpm = calc_cut_marginal_tpm(&partition, &cloned_tpm);
+                let partitioned_cause_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &partitioned_tpm);
+                let partitioned_effect_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &partitioned_tpm);
+                let partitioned = try_search_constellation_with_parts(&partitioned_cause_parts, &partitioned_effect_parts, &partitioned_config)?;
+
+                let emd = try_calc_constellation_distance(&cloned_criterion, &partitioned, config.distance)?;
+
+                if !challenge_update(index, emd, partition, &cloned_search, &config) {
                     break;
-                }
+                };
             };
+
+            Ok(())
         });
 
         handles.push(handle);
     });
 
-    loop {
-        if let Some(handle) = handles.pop() {
-            handle.join().unwrap();
-        } else {
-            break;
-        }
+    // join every worker before returning the first error
+    let mut result = Ok(());
+    while let Some(handle) = handles.pop() {
+        let joined = handle.join().unwrap_or(Err(PhiError::WorkerPanicked));
+        result = result.and(joined);
     };
+    result?;
 
-    let mut final_mip = Arc::try_unwrap(mip).unwrap().into_inner().unwrap();
-    if final_mip.phi == f64::INFINITY { // no possible partition found
-        final_mip.phi = 0.0;
+    if cancel.is_cancelled() {
+        return Err(PhiError::Cancelled);
     }
 
+    let final_mip = select_mip(Arc::try_unwrap(search).unwrap().into_inner().map_err(|_| PhiError::WorkerPanicked)?, config);
+
     let mut unwrapped = Arc::try_unwrap(criterion).unwrap();
     unwrapped.mip = final_mip;
-    unwrapped
+    Ok(unwrapped)
+}
+
+pub fn search_constellation_with_mip(current_state: usize, tpm: &Arc<na::DMatrix<f64>>, config: &AnalysisConfig) -> Constellation {
+    try_search_constellation_with_mip(current_state, tpm, config, &CancellationToken::new()).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Complex {
     pub elements: Vec<usize>,
     pub marginal_tpm: na::DMatrix<f64>,
     pub constellation: Constellation
 }
 
-fn notify_progress(candidate: &Vec<usize>, phi: f64, current_count: usize, total_count: usize, start_time: SystemTime) {
-    let progress = format!("PROGRESS={}/{}", current_count, total_count);
-    let candidate = format!("CANDIDATE={:?}", candidate);
-    let phi = format!("BIG_PHI={}", phi);
-    let time = format!("TIME={:.2e}", start_time.elapsed().unwrap().as_secs_f64());
+pub fn try_search_complex(current_state: usize, tpm: &na::DMatrix<f64>, config: &AnalysisConfig, observer: &dyn ProgressObserver, cancel: &CancellationToken) -> Result<Complex> {
+    // `config.log` is ignored, `observer` is notified instead
+    config.validate()?;
 
-    println!("{}, {}, {}, {}", progress, candidate, phi, time);
-}
-
-pub fn search_complex(current_state: usize, tpm: &na::DMatrix<f64>, num_threads: usize, log: bool) -> Complex {
-    let system_basis = BitBasis::construct_from_max_image_size(tpm.ncols());
+    let system_basis: BitBasis = BitBasis::construct_from_max_image_size(tpm.ncols());
     let max_image_size = system_basis.max_image_size();
 
     let total_count = max_image_size - 1;
+    let search_start_time = Instant::now();
 
     let mut current_complex: Option<Complex> = None;
 
-    (1..max_image_size).for_each(|mask| {
-        let start_time = SystemTime::now();
+    for mask in 1..max_image_size {
+        if cancel.is_cancelled() {
+            return Err(PhiError::Cancelled);
+        }
+
+        let start_time = Instant::now();
 
         let candidate_elements: Vec<usize> = (0..system_basis.max_dim).filter(|&i| mask & USIZE_BASIS[i] != 0).collect();
         let candidate_basis = system_basis.sub_basis(candidate_elements.as_slice());
 
-        let marginal = Arc::new(calc_fixed_marginal_tpm(&candidate_basis, current_state, &tpm));
+        let marginal = Arc::new(calc_background_marginal_tpm(&candidate_basis, current_state, &config.background, tpm));
+        let marginal_state = candidate_basis.compress(&current_state);
 
-        let constellation = search_constellation_with_mip(current_state, &marginal, num_threads);
+        let constellation = try_search_constellation_with_mip(marginal_state, &marginal, config, cancel)?;
 
-        if log {
-            notify_progress(&&candidate_elements, constellation.mip.phi, mask, total_count, start_time);
-        };
+        observer.notify(&Progress {
+            candidate: &candidate_elements,
+            phi: constellation.mip.phi,
+            current_count: mask,
+            total_count,
+            elapsed: start_time.elapsed(),
+            total_elapsed: search_start_time.elapsed(),
+        });
 
         let update = if let Some(complex) = &current_complex {
-            constellation.mip.phi > complex.constellation.mip.phi
+            matches!(compare_within(constellation.mip.phi, complex.constellation.mip.phi, config.tolerance), Comparison::NotEqual(diff) if diff.is_sign_positive())
         } else {
             true
         };
@@ -192,10 +264,15 @@ pub fn search_complex(current_state: usize, tpm: &na::DMatrix<f64>, num_threads:
             current_complex = Some(Complex {
                 elements: candidate_elements,
                 marginal_tpm: Arc::try_unwrap(marginal).unwrap(),
-                constellation: constellation,
+                constellation,
             });
         };
-    });
+    };
+
+    current_complex.ok_or_else(|| PhiError::InvalidTpm(format!("{}x{} TPM has no candidate of complex", tpm.nrows(), tpm.ncols())))
+}
 
-    current_complex.unwrap()
+pub fn search_complex(current_state: usize, tpm: &na::DMatrix<f64>, config: &AnalysisConfig) -> Complex {
+    let observer: &dyn ProgressObserver = if config.log { &LogObserver } else { &SilentObserver };
+    try_search_complex(current_state, tpm, config, observer, &CancellationToken::new()).unwrap_or_else(|e| panic!("{}", e))
 }
@@ -1,6 +1,6 @@
-use std::{sync::Arc, usize};
+use std::sync::{Arc, Mutex};
 use nalgebra as na;
-use crate::{basis::BitBasis, compare::{Comparison, compare_roughly}, emd::{calc_constellation_emd, calc_repertoire_emd}, mechanism::{generate_all_repertoire_parts, search_concept_with_parts}, partition::SystemPartition, repertoire::{calc_cause_repertoire, calc_effect_repertoire, normalize_repertoire}, system::{search_complex, search_constellation_with_mip, search_constellation_with_parts}, tpm::calc_partitioned_marginal_tpm};
+use crate::{basis::BitBasis, bitwise::{BitSet, HeapBitSet, generate_indices, generate_mask}, compare::{Comparison, DEFAULT_TOLERANCE, compare_roughly, compare_within}, config::AnalysisConfig, emd::{DistanceMeasure, calc_constellation_emd, calc_repertoire_distance, calc_repertoire_emd, try_calc_repertoire_distance, try_calc_repertoire_emd}, error::PhiError, link_fn::{BoxedLink, Link, try_get_link_fns, try_get_link_model}, sif::{LinkInfo, LinkType, parse_sif_str, read_sif_from, try_read_sif, write_sif}, mechanism::{generate_all_repertoire_parts, search_concept_with_parts, search_core_with_parts}, network::{Network, format_elements, format_repertoire, format_state_bits}, partition::{CutDirection, DirectedPartition, MechanismPart, MechanismPartition, MechanismPartitionIterator, MechanismPartitionScheme, SystemCut, SystemPartition, SystemPartitionIterator, SystemPartitionScheme, generate_directed_partitions, generate_mechanism_partitions, generate_system_cuts}, repertoire::{calc_cause_repertoire, calc_effect_repertoire, calc_link_cause_repertoire, calc_link_effect_repertoire, normalize_repertoire}, progress::{CancellationToken, Progress, ProgressObserver, SilentObserver}, system::{search_complex, search_constellation_with_mip, search_constellation_with_parts, try_search_complex, try_search_constellation_with_mip}, tpm::{BackgroundCondition, StateByNodeTpm, TransitionModel, calc_background_marginal_tpm, calc_cut_marginal_tpm, calc_fixed_marginal_tpm, calc_link_marginal_tpm, calc_partitioned_marginal_tpm, calc_state_by_node_tpm, calc_tpm, validate}, tpm_file::{TpmFormat, parse_tpm_str, read_tpm}};
 
 
 fn notify_pass(case_number: usize) {
@@ -99,6 +99,14 @@ fn generate_reference_tpm() -> na::DMatrix::<f64> {
     na::DMatrix::<f64>::from_row_slice(8, 8, values)
 }
 
+fn generate_swap_tpm() -> na::DMatrix::<f64> {
+    // A and B copy each other
+    na::DMatrix::<f64>::from_fn(4, 4, |row, col| {
+        let swapped = ((row & 1) << 1) | (row >> 1);
+        if col == swapped { 1.0 } else { 0.0 }
+    })
+}
+
 #[test]
 fn test_calc_cause_repertoire() {
     let tpm = generate_reference_tpm();
@@ -321,7 +329,7 @@ fn test_search_concept_with_parts() {
 
     (0..mechanisms.len()).for_each(|i| {
         let mechanism = BitBasis::construct_from_mask(mechanisms[i], 3);
-        let concept = search_concept_with_parts(&mechanism, &cause_parts, &effect_parts);
+        let concept = search_concept_with_parts(&mechanism, &cause_parts, &effect_parts, &AnalysisConfig::default());
         let actual = concept.phi;
 
         assert_almost_equal_scalar(actual, expected[i]);
@@ -373,8 +381,8 @@ fn test_calc_constellation_emd() {
     let partitioned_cause_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &partitioned_tpm);
     let partitioned_effect_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &partitioned_tpm);
 
-    let intact = search_constellation_with_parts(&intact_cause_parts, &intact_effect_parts);
-    let partitioned = search_constellation_with_parts(&partitioned_cause_parts, &partitioned_effect_parts);
+    let intact = search_constellation_with_parts(&intact_cause_parts, &intact_effect_parts, &AnalysisConfig::default());
+    let partitioned = search_constellation_with_parts(&partitioned_cause_parts, &partitioned_effect_parts, &AnalysisConfig::default());
 
     let extended_emd = calc_constellation_emd(&intact, &partitioned);
 
@@ -386,11 +394,10 @@ fn test_search_constellation_with_mip() {
     let current_state = generate_reference_state();
     let tpm = Arc::new(generate_reference_tpm());
 
-    let constellation = search_constellation_with_mip(current_state, &tpm, 1);
+    let constellation = search_constellation_with_mip(current_state, &tpm, &AnalysisConfig::default());
     let mip = constellation.mip;
 
-    assert_eq!(mip.partition.cut_from, [0, 1]);
-    assert_eq!(mip.partition.cut_to, [2]);
+    assert_eq!(mip.partition, SystemCut::Unidirectional(SystemPartition { cut_from: vec![0, 1], cut_to: vec![2] }));
     assert_almost_equal_scalar(mip.phi, 1.9166666666);
 }
 
@@ -399,8 +406,1026 @@ fn test_search_complex() {
     let current_state = generate_reference_state();
     let tpm = generate_reference_tpm();
 
-    let complex = search_complex(current_state, &tpm, 2, false);
+    let config = AnalysisConfig::builder().num_threads(2).build().unwrap();
+    let complex = search_complex(current_state, &tpm, &config);
 
     assert_eq!(complex.elements, [0, 1, 2]);
     assert_almost_equal_scalar(complex.constellation.mip.phi, 1.9166666666);
 }
+
+#[test]
+fn test_search_complex_of_non_prefix() {
+    // A is isolated and the complex is BC, whose state must be taken from B and C, not from A and B
+    let tpm = calc_tpm(try_get_link_fns(parse_sif_str("A COPY A\nB AND B C\nC OR B C\n").unwrap()).unwrap(), 1);
+    let current_state = 0b110;
+    let config = AnalysisConfig::default();
+
+    let complex = search_complex(current_state, &tpm, &config);
+    assert_eq!(complex.elements, [1, 2]);
+    assert_almost_equal_scalar(complex.constellation.mip.phi, 0.0902777777);
+
+    let marginal = Arc::new(complex.marginal_tpm);
+    let misread = search_constellation_with_mip(current_state & 0b11, &marginal, &config);
+    assert_almost_equal_scalar(misread.mip.phi, 0.0694444444);
+}
+
+fn generate_link_info(element: &str, link_type: LinkType, condition: &[&str]) -> LinkInfo {
+    LinkInfo {
+        element: element.to_string(),
+        link_type,
+        parameters: vec![],
+        condition: condition.iter().map(|c| c.to_string()).collect(),
+        weights: vec![1.0; condition.len()],
+    }
+}
+
+#[test]
+fn test_phi_error() {
+    // CASE 0, missing file
+    assert!(matches!(try_read_sif("not/existing.sif"), Err(PhiError::Io(_))));
+    notify_pass(0);
+
+    // CASE 1, duplicated element
+    let infos = vec![
+        generate_link_info("A", LinkType::COPY, &["A"]),
+        generate_link_info("A", LinkType::NOT, &["A"]),
+    ];
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::DuplicateElement(_))));
+    notify_pass(1);
+
+    // CASE 2, undefined element
+    let infos = vec![
+        generate_link_info("A", LinkType::COPY, &["B"]),
+    ];
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::UndefinedElement { .. })));
+    notify_pass(2);
+
+    // CASE 3, invalid condition size
+    let infos = vec![
+        generate_link_info("A", LinkType::COPY, &["A", "A"]),
+    ];
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidCondition { .. })));
+    notify_pass(3);
+
+    // CASE 4, repertoires with different total mass
+    let vec_from = na::DVector::<f64>::from_column_slice(&[1.0, 0.0]);
+    let vec_to = na::DVector::<f64>::from_column_slice(&[0.5, 0.0]);
+    assert!(matches!(try_calc_repertoire_emd(&vec_from, &vec_to), Err(PhiError::LpInfeasible)));
+    notify_pass(4);
+
+    // CASE 5, repertoires of different or no states
+    let vec_to = na::DVector::<f64>::from_column_slice(&[1.0]);
+    assert!(matches!(try_calc_repertoire_emd(&vec_from, &vec_to), Err(PhiError::InvalidDimension { from: 2, to: 1 })));
+    assert!(matches!(try_calc_repertoire_distance(&vec_from, &vec_to, DistanceMeasure::L1), Err(PhiError::InvalidDimension { .. })));
+    let empty = na::DVector::<f64>::zeros(0);
+    assert!(matches!(try_calc_repertoire_emd(&empty, &empty), Err(PhiError::InvalidDimension { from: 0, to: 0 })));
+    notify_pass(5);
+
+    // CASE 6, system without elements has no complex
+    let tpm = na::DMatrix::<f64>::from_element(1, 1, 1.0);
+    let result = try_search_complex(0, &tpm, &AnalysisConfig::default(), &SilentObserver, &CancellationToken::new());
+    assert!(matches!(result, Err(PhiError::InvalidTpm(_))));
+    notify_pass(6);
+
+    // CASE 7, ring larger than masks of links
+    let names: Vec<String> = (0..65).map(|i| format!("N{}", i)).collect();
+    let generate_ring = || (0..65).map(|i| generate_link_info(&names[i], LinkType::COPY, &[&names[(i + 64) % 65]])).collect::<Vec<LinkInfo>>();
+    assert!(matches!(try_get_link_fns(generate_ring()), Err(PhiError::InvalidSystemSize(65))));
+    assert!(matches!(Network::from_link_infos(generate_ring(), 1), Err(PhiError::InvalidSystemSize(65))));
+    notify_pass(7);
+}
+
+struct TemporaryFile {
+    path: String,
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn write_temporary_file(name: &str, content: &str) -> TemporaryFile {
+    // unique among processes running tests at once, removed when dropped
+    let path = std::env::temp_dir().join(format!("rust_phi_{}_{}", std::process::id(), name));
+    std::fs::write(&path, content).unwrap();
+    TemporaryFile { path: path.to_str().unwrap().to_string() }
+}
+
+#[test]
+fn test_try_read_sif() {
+    // CASE 0, comments, blank lines and irregular whitespace
+    let file = write_temporary_file("read_sif_0.sif", "# annotated network\n\nA\tANY  B C # inline\r\n  B AND A C\nC XOR A B\n");
+    let infos = try_read_sif(&file.path).unwrap();
+    assert_eq!(infos.len(), 3);
+    assert_eq!(infos[0].element, "A");
+    assert_eq!(infos[0].condition, ["B", "C"]);
+    assert_eq!(infos[1].element, "B");
+    notify_pass(0);
+
+    // CASE 1, unknown link type
+    let file = write_temporary_file("read_sif_1.sif", "A COPY A\nB  NAN A\n");
+    match try_read_sif(&file.path) {
+        Err(PhiError::Sif { file: path, line, column, .. }) => {
+            assert_eq!(path, Some(file.path.clone()));
+            assert_eq!((line, column), (2, 4));
+        },
+        _ => panic!("Unknown link type is not reported"),
+    }
+    notify_pass(1);
+
+    // CASE 2, missing condition
+    match parse_sif_str("A COPY # A\n") {
+        Err(PhiError::Sif { line, column, .. }) => assert_eq!((line, column), (1, 7)),
+        _ => panic!("Missing condition is not reported"),
+    }
+    notify_pass(2);
+}
+
+#[test]
+fn test_sif_round_trip() {
+    let sif = "A ANY B C D\nB AND A C # comment\nC XOR A B\n\nD COPY D\n";
+    let infos = parse_sif_str(sif).unwrap();
+
+    let mut written = Vec::<u8>::new();
+    write_sif(&mut written, &infos).unwrap();
+    assert_eq!(String::from_utf8(written.clone()).unwrap(), "A ANY B C D\nB AND A C\nC XOR A B\nD COPY D\n");
+
+    let read = read_sif_from(written.as_slice()).unwrap();
+    assert_eq!(read, infos);
+}
+
+#[test]
+fn test_large_bit_basis() {
+    // CASE 0, heap-backed bitset beyond 64 elements
+    let mut heap: HeapBitSet = generate_mask(&[3, 64, 150]);
+    assert_eq!(generate_indices(&heap), [3, 64, 150]);
+    assert_eq!(heap.bit_len(), 151);
+    let mut carried: HeapBitSet = generate_mask(&(0..64).collect::<Vec<usize>>());
+    carried.increment();
+    assert_eq!(carried, HeapBitSet::singleton(64));
+    heap.union_with(&carried);
+    assert_eq!(heap.count_ones(), 3);
+    notify_pass(0);
+
+    // CASE 1, compress and expand a state of a 100-element system
+    let candidate = BitBasis::<u128>::construct_from_mask(generate_mask(&[2, 70, 99]), 100);
+    let state: u128 = generate_mask(&[0, 70, 99]);
+    assert_eq!(candidate.compress(&state), 0b110);
+    assert_eq!(candidate.expand(0b110, 1), state);
+    assert_eq!(candidate.fixed_state(&state), generate_mask(&[70, 99]));
+    assert_eq!(candidate.generate_complement_basis().dim, 97);
+    notify_pass(1);
+
+    // CASE 2, every backend enumerates the same partitions
+    let default_partitions: Vec<_> = SystemPartitionIterator::<usize>::construct(4).map(|p| (p.cut_from, p.cut_to)).collect();
+    let heap_partitions: Vec<_> = SystemPartitionIterator::<HeapBitSet>::construct(4).map(|p| (p.cut_from, p.cut_to)).collect();
+    assert_eq!(default_partitions.len(), 14);
+    assert_eq!(default_partitions, heap_partitions);
+
+    let default_count = MechanismPartitionIterator::<usize>::construct(2, 3).count();
+    let heap_count = MechanismPartitionIterator::<HeapBitSet>::construct(2, 3).count();
+    assert_eq!(default_count, 15);
+    assert_eq!(default_count, heap_count);
+    notify_pass(2);
+}
+
+#[test]
+fn test_link_model() {
+    // weights, tables, noise and a repeated input change the indices given to links
+    let sif = "A OR B C\nB THRESHOLD:0.5:0.9 A:0.75 C:-0.25\nC TABLE:0.1:0.8:0.3:0.6 A B\nD XOR:0.7 A C A\n";
+    let tpm = calc_tpm(try_get_link_fns(parse_sif_str(sif).unwrap()).unwrap(), 1);
+    let model = try_get_link_model(parse_sif_str(sif).unwrap()).unwrap();
+    assert_eq!(model.element_size(), 4);
+    assert_eq!(model.links[3].inputs, vec![0, 2]);
+
+    // CASE 0, same repertoires as the ones from the TPM, restricted to the purview
+    let system_basis: BitBasis = BitBasis::construct_from_max_dim(4);
+    [0b0000, 0b0101, 0b1011].iter().for_each(|&current_state| {
+        (0..16).for_each(|purview_mask| {
+            (0..16).for_each(|mechanism_mask| {
+                let purview = BitBasis::construct_from_mask(purview_mask, 4);
+                let mechanism = BitBasis::construct_from_mask(mechanism_mask, 4);
+
+                let full_cause = calc_cause_repertoire(&purview, &mechanism, current_state, &tpm);
+                let full_effect = calc_effect_repertoire(&purview, &mechanism, current_state, &tpm);
+                let cause = calc_link_cause_repertoire(&purview, &mechanism, &current_state, &model);
+                let effect = calc_link_effect_repertoire(&purview, &mechanism, &current_state, &model);
+
+                (0..purview.image_size()).for_each(|index| {
+                    let state = purview.expand(index, 0);
+                    assert_eq!(purview.compress(&state), index);
+                    assert_almost_equal_scalar(cause[index], full_cause[state]);
+                    assert_almost_equal_scalar(effect[index], full_effect[state]);
+                });
+            });
+        });
+    });
+    notify_pass(0);
+
+    // CASE 1, same marginal TPMs as the ones from the TPM, in the order of basis vectors
+    let conditions = [BackgroundCondition::Current, BackgroundCondition::Marginalized, BackgroundCondition::Current, BackgroundCondition::Fixed(true)];
+    [vec![0, 2], vec![2, 0], vec![1, 3], vec![3]].iter().for_each(|indices| {
+        let surviving_basis = system_basis.sub_basis(indices);
+        let expected = calc_background_marginal_tpm(&surviving_basis, 0b0101, &conditions, &tpm);
+        let actual = calc_link_marginal_tpm(&surviving_basis, &0b0101, &conditions, &model);
+        assert_almost_equal_matrix(&actual, &expected);
+    });
+    notify_pass(1);
+
+    // CASE 2, a ring of 100 COPY elements, too large for a TPM
+    let sif: String = (0..100).map(|i| format!("N{} COPY:0.9 N{}\n", i, (i + 99) % 100)).collect();
+    let model = try_get_link_model(parse_sif_str(&sif).unwrap()).unwrap();
+    let state: HeapBitSet = generate_mask(&[98]);
+
+    let purview = BitBasis::<HeapBitSet>::construct_from_mask(generate_mask(&[99]), 100);
+    let mechanism = BitBasis::<HeapBitSet>::construct_from_mask(generate_mask(&[98]), 100);
+    let effect = calc_link_effect_repertoire(&purview, &mechanism, &state, &model);
+    assert_almost_equal_vec(&effect, &na::DVector::<f64>::from_vec(vec![0.1, 0.9]));
+    let cause = calc_link_cause_repertoire(&mechanism, &purview, &state, &model);
+    assert_almost_equal_vec(&cause, &na::DVector::<f64>::from_vec(vec![0.9, 0.1]));
+
+    let surviving_basis = BitBasis::<HeapBitSet>::construct_from_mask(generate_mask(&[0, 99]), 100);
+    let marginal = calc_link_marginal_tpm(&surviving_basis, &state, &[], &model);
+    let expected = na::DMatrix::<f64>::from_row_slice(4, 4, &[ // N99 follows N98 which is fixed to ON
+        0.09, 0.01, 0.81, 0.09,
+        0.09, 0.01, 0.81, 0.09,
+        0.01, 0.09, 0.09, 0.81,
+        0.01, 0.09, 0.09, 0.81,
+    ]);
+    assert_almost_equal_matrix(&marginal, &expected);
+    notify_pass(2);
+}
+
+struct ReliableCopy {
+    reliability: f64,
+}
+
+impl Link for ReliableCopy {
+    fn probability(&self, env: usize, mask: usize) -> f64 {
+        if env & mask == 0 {
+            1.0 - self.reliability
+        } else {
+            self.reliability
+        }
+    }
+}
+
+#[test]
+fn test_calc_tpm_with_links() {
+    let links: Vec<(BoxedLink, usize)> = vec![
+        (Box::new(ReliableCopy { reliability: 0.9 }), 0b10), // A copies B
+        (Box::new(|_env: usize, _mask: usize| 0.25), 0b01), // B is ON with a fixed probability
+    ];
+
+    let tpm = calc_tpm(links, 2);
+
+    let expected_tpm = na::DMatrix::<f64>::from_row_slice(4, 4, &[
+        0.675, 0.075, 0.225, 0.025,
+        0.675, 0.075, 0.225, 0.025,
+        0.075, 0.675, 0.025, 0.225,
+        0.075, 0.675, 0.025, 0.225,
+    ]);
+
+    assert_almost_equal_matrix(&tpm, &expected_tpm);
+}
+
+#[test]
+fn test_threshold_link() {
+    let sif = "A THRESHOLD:1.0 B:0.6 C:0.6\nB THRESHOLD:0.5 A B:-1\nC COPY C\n";
+    let infos = parse_sif_str(sif).unwrap();
+    assert_eq!(infos[0].parameters, [1.0]);
+    assert_eq!(infos[1].weights, [1.0, -1.0]);
+
+    let mut written = Vec::<u8>::new();
+    write_sif(&mut written, &infos).unwrap();
+    assert_eq!(read_sif_from(written.as_slice()).unwrap(), infos);
+
+    let links = try_get_link_fns(infos).unwrap();
+    let (a, a_mask) = &links[0];
+    let (b, b_mask) = &links[1];
+
+    // CASE 0, A fires only when B and C are ON
+    assert_almost_equal_scalar(a.probability(0b110, *a_mask), 1.0);
+    assert_almost_equal_scalar(a.probability(0b010, *a_mask), 0.0);
+    assert_almost_equal_scalar(a.probability(0b101, *a_mask), 0.0);
+    notify_pass(0);
+
+    // CASE 1, B is inhibited by itself
+    assert_almost_equal_scalar(b.probability(0b001, *b_mask), 1.0);
+    assert_almost_equal_scalar(b.probability(0b011, *b_mask), 0.0);
+    notify_pass(1);
+
+    // CASE 2, invalid parameters
+    let infos = parse_sif_str("A AND A:0.5 A\n").unwrap();
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidParameter { .. })));
+    let infos = parse_sif_str("A THRESHOLD A\n").unwrap();
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidParameter { .. })));
+    match parse_sif_str("A THRESHOLD:1.0 A:x\n") {
+        Err(PhiError::Sif { line, column, .. }) => assert_eq!((line, column), (1, 19)),
+        _ => panic!("Invalid weight is not reported"),
+    }
+    notify_pass(2);
+}
+
+#[test]
+fn test_noisy_link() {
+    let sif = "A COPY:0.8 B\nB NOT:0.75 A\nC NOISY:0.2 C\nD THRESHOLD:1.5:0.9 A B\n";
+    let links = try_get_link_fns(parse_sif_str(sif).unwrap()).unwrap();
+    let probabilities: Vec<f64> = links.iter().map(|(link, mask)| link.probability(0b0010, *mask)).collect();
+
+    assert_almost_equal_vec(&na::DVector::<f64>::from_vec(probabilities), &na::DVector::<f64>::from_column_slice(&[0.8, 0.75, 0.2, 0.1]));
+
+    let tpm = calc_tpm(try_get_link_fns(parse_sif_str("A COPY:0.8 B\nB NOT:0.75 A\n").unwrap()).unwrap(), 1);
+    let expected_tpm = na::DMatrix::<f64>::from_row_slice(4, 4, &[
+        0.2, 0.05, 0.6, 0.15,
+        0.6, 0.15, 0.2, 0.05,
+        0.05, 0.2, 0.15, 0.6,
+        0.15, 0.6, 0.05, 0.2,
+    ]);
+    assert_almost_equal_matrix(&tpm, &expected_tpm);
+
+    let infos = parse_sif_str("A AND:1.5 A A\n").unwrap();
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidParameter { .. })));
+    let infos = parse_sif_str("A AND:0.5:0.5 A A\n").unwrap();
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidParameter { .. })));
+}
+
+#[test]
+fn test_sigmoid_link() {
+    let sif = "A SIGMOID:0.5:0.25 B:2 C:-1\nB SIGMOID:0:1 A\nC SIGMOID:1:2:0.5 A\n";
+    let infos = parse_sif_str(sif).unwrap();
+
+    let mut written = Vec::<u8>::new();
+    write_sif(&mut written, &infos).unwrap();
+    assert_eq!(read_sif_from(written.as_slice()).unwrap(), infos);
+
+    let links = try_get_link_fns(infos).unwrap();
+    let sigmoid = |x: f64| 1.0 / (1.0 + (-x).exp());
+
+    // CASE 0, weighted sum of B and C
+    let (a, a_mask) = &links[0];
+    assert_almost_equal_scalar(a.probability(0b000, *a_mask), sigmoid(-0.5 / 0.25));
+    assert_almost_equal_scalar(a.probability(0b010, *a_mask), sigmoid(1.5 / 0.25));
+    assert_almost_equal_scalar(a.probability(0b110, *a_mask), sigmoid(0.5 / 0.25));
+    notify_pass(0);
+
+    // CASE 1, graded probability without bias
+    let (b, b_mask) = &links[1];
+    assert_almost_equal_scalar(b.probability(0b000, *b_mask), 0.5);
+    assert_almost_equal_scalar(b.probability(0b001, *b_mask), sigmoid(1.0));
+    notify_pass(1);
+
+    // CASE 2, fully noisy sigmoid
+    let (c, c_mask) = &links[2];
+    assert_almost_equal_scalar(c.probability(0b001, *c_mask), 0.5);
+    notify_pass(2);
+
+    let infos = parse_sif_str("A SIGMOID:0:0 A\n").unwrap();
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidParameter { .. })));
+}
+
+#[test]
+fn test_gates_with_arbitrary_fan_in() {
+    let sif = "A AND A B C\nB OR A B C\nC XOR A B C\nD NAND A B C\nE NOR A B C\nF XNOR A B C\nG MAJ A B C\nH MIN A B C\n";
+    let links = try_get_link_fns(parse_sif_str(sif).unwrap()).unwrap();
+
+    let expected: [[f64; 8]; 8] = [
+        [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0], // AND
+        [0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0], // OR
+        [0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0], // XOR
+        [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0], // NAND
+        [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0], // NOR
+        [1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0], // XNOR
+        [0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0], // MAJ
+        [1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0], // MIN
+    ];
+
+    links.iter().zip(expected.iter()).enumerate().for_each(|(i, ((link, mask), e))| {
+        let actual: Vec<f64> = (0..8).map(|env| link.probability(env, *mask)).collect();
+        assert_almost_equal_vec(&na::DVector::<f64>::from_vec(actual), &na::DVector::<f64>::from_column_slice(e));
+        notify_pass(i);
+    });
+}
+
+#[test]
+fn test_table_link() {
+    // A is ON with 0.9 only when B=ON and C=OFF, C is a noisy copy of B
+    let sif = "A TABLE:0:0:0.9:0 C B\nB COPY B\nC TABLE:0.1:0.8 B\n";
+    let infos = parse_sif_str(sif).unwrap();
+
+    let mut written = Vec::<u8>::new();
+    write_sif(&mut written, &infos).unwrap();
+    assert_eq!(read_sif_from(written.as_slice()).unwrap(), infos);
+
+    let links = try_get_link_fns(infos).unwrap();
+
+    let (a, a_mask) = &links[0];
+    let actual: Vec<f64> = (0..8).map(|env| a.probability(env, *a_mask)).collect();
+    assert_almost_equal_vec(&na::DVector::<f64>::from_vec(actual), &na::DVector::<f64>::from_column_slice(&[0.0, 0.0, 0.9, 0.9, 0.0, 0.0, 0.0, 0.0]));
+
+    let (c, c_mask) = &links[2];
+    assert_almost_equal_scalar(c.probability(0b000, *c_mask), 0.1);
+    assert_almost_equal_scalar(c.probability(0b010, *c_mask), 0.8);
+
+    let infos = parse_sif_str("A TABLE:0:1:1 A B\nB COPY B\n").unwrap();
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidParameter { .. })));
+    let infos = parse_sif_str("A TABLE:0:2 A\n").unwrap();
+    assert!(matches!(try_get_link_fns(infos), Err(PhiError::InvalidParameter { .. })));
+}
+
+#[test]
+fn test_read_tpm() {
+    let tpm = generate_reference_tpm();
+
+    // CASE 0, state-by-node in CSV
+    let state_by_node = "# A, B, C\n0,0,0\n0,0,1\n1,0,1\n1,0,0\n1,0,0\n1,1,1\n1,0,1\n1,1,0\n";
+    let file = write_temporary_file("read_tpm_0.csv", state_by_node);
+    assert_almost_equal_matrix(&read_tpm(&file.path, TpmFormat::StateByNode).unwrap(), &tpm);
+    notify_pass(0);
+
+    // CASE 1, state-by-state in plain text
+    let state_by_state: Vec<String> = tpm.row_iter().map(|row| {
+        row.iter().map(|x| x.to_string()).collect::<Vec<String>>().join("  ")
+    }).collect();
+    let actual = parse_tpm_str(&state_by_state.join("\n"), TpmFormat::StateByState).unwrap();
+    assert_almost_equal_matrix(&actual, &tpm);
+    notify_pass(1);
+
+    // CASE 2, graded state-by-node
+    let actual = parse_tpm_str("0.5 # comment after values\n0.25\n", TpmFormat::StateByNode).unwrap();
+    assert_almost_equal_matrix(&actual, &na::DMatrix::<f64>::from_row_slice(2, 2, &[0.5, 0.5, 0.75, 0.25]));
+    notify_pass(2);
+
+    // CASE 3, invalid files
+    assert!(matches!(parse_tpm_str("0 1 0\n1 0 0\n0 0 1\n", TpmFormat::StateByState), Err(PhiError::InvalidTpm(_))));
+    assert!(matches!(parse_tpm_str("0\n1\n", TpmFormat::StateByState), Err(PhiError::InvalidTpm(_))));
+    assert!(matches!(parse_tpm_str("0, 1\n1\n", TpmFormat::StateByState), Err(PhiError::TpmFile { line: 2, .. })));
+    match parse_tpm_str("0, 1\n1, 0.x\n", TpmFormat::StateByState) {
+        Err(PhiError::TpmFile { line, column, .. }) => assert_eq!((line, column), (2, 4)),
+        _ => panic!("Invalid number is not reported"),
+    }
+    notify_pass(3);
+}
+
+#[test]
+fn test_state_by_node_tpm() {
+    let tpm = generate_reference_tpm();
+    let current_state = generate_reference_state();
+
+    // CASE 0, lossless conversion
+    let state_by_node = StateByNodeTpm::from_state_by_state(&tpm).unwrap();
+    assert_eq!(state_by_node.element_size(), 3);
+    assert_almost_equal_matrix(&state_by_node.to_state_by_state(), &tpm);
+    (0..8).for_each(|from| (0..8).for_each(|to| {
+        assert_almost_equal_scalar(state_by_node.transition(from, to), tpm.transition(from, to));
+    }));
+    notify_pass(0);
+
+    // CASE 1, repertoires are the same as state-by-state
+    let expected_cause = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &tpm);
+    let expected_effect = generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &tpm);
+    let actual_cause = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &state_by_node);
+    let actual_effect = generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &state_by_node);
+    assert_almost_equal_matrix(&actual_cause, &expected_cause);
+    assert_almost_equal_matrix(&actual_effect, &expected_effect);
+    notify_pass(1);
+
+    // CASE 2, built directly from link functions
+    let sif = "A COPY:0.8 B\nB NOT:0.75 A\n";
+    let from_links = calc_state_by_node_tpm(try_get_link_fns(parse_sif_str(sif).unwrap()).unwrap()).unwrap();
+    let expected_tpm = calc_tpm(try_get_link_fns(parse_sif_str(sif).unwrap()).unwrap(), 1);
+    assert_almost_equal_matrix(&from_links.probs, &na::DMatrix::<f64>::from_row_slice(4, 2, &[0.2, 0.75, 0.2, 0.25, 0.8, 0.75, 0.8, 0.25]));
+    assert_almost_equal_matrix(&from_links.to_state_by_state(), &expected_tpm);
+    notify_pass(2);
+
+    assert!(matches!(StateByNodeTpm::construct(na::DMatrix::<f64>::zeros(4, 3)), Err(PhiError::InvalidTpm(_))));
+    assert!(matches!(StateByNodeTpm::from_state_by_state(&na::DMatrix::<f64>::zeros(4, 2)), Err(PhiError::InvalidTpm(_))));
+    assert!(matches!(StateByNodeTpm::from_state_by_state(&na::DMatrix::<f64>::zeros(6, 6)), Err(PhiError::InvalidTpm(_))));
+}
+
+#[test]
+fn test_validate_tpm() {
+    // CASE 0, valid
+    let report = validate(&generate_reference_tpm(), generate_reference_state());
+    assert!(report.is_valid());
+    notify_pass(0);
+
+    // CASE 1, unreachable state
+    let report = validate(&generate_reference_tpm(), 0b110);
+    assert!(!report.reachable);
+    assert!(!report.is_valid());
+    notify_pass(1);
+
+    // CASE 2, broken rows
+    let tpm = na::DMatrix::<f64>::from_row_slice(4, 4, &[
+        0.25, 0.25, 0.25, 0.25,
+        0.5, 0.0, 0.0, 0.5, // A and B are correlated
+        1.5, -0.5, 0.0, 0.0,
+        0.5, 0.5, 0.5, 0.0,
+    ]);
+    let report = validate(&tpm, 0);
+    assert!(report.reachable);
+    assert_eq!(report.unnormalized_rows.len(), 1);
+    assert_eq!(report.unnormalized_rows[0].0, 3);
+    assert_eq!(report.negative_entries, [(2, 1)]);
+    assert_eq!(report.dependent_rows, [1, 3]);
+    notify_pass(2);
+
+    // CASE 3, invalid shape
+    assert!(!validate(&na::DMatrix::<f64>::zeros(3, 3), 0).valid_shape);
+    assert!(!validate(&na::DMatrix::<f64>::zeros(4, 2), 0).valid_shape);
+    notify_pass(3);
+}
+
+#[test]
+fn test_calc_background_marginal_tpm() {
+    let tpm = generate_reference_tpm();
+    let current_state = generate_reference_state();
+    let candidate = BitBasis::construct_from_mask(0b011, 3);
+
+    let off_tpm = na::DMatrix::<f64>::from_row_slice(4, 4, &[
+        1.0, 0.0, 0.0, 0.0,
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+    ]);
+
+    let on_tpm = na::DMatrix::<f64>::from_row_slice(4, 4, &[
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    ]);
+
+    // CASE 0, C is fixed to the current state (OFF)
+    let actual = calc_background_marginal_tpm(&candidate, current_state, &[BackgroundCondition::Current; 3], &tpm);
+    assert_almost_equal_matrix(&actual, &off_tpm);
+    assert_almost_equal_matrix(&calc_fixed_marginal_tpm(&candidate, current_state, &tpm), &off_tpm);
+    notify_pass(0);
+
+    // CASE 1, C is fixed to ON
+    let conditions = [BackgroundCondition::Current, BackgroundCondition::Current, BackgroundCondition::Fixed(true)];
+    let actual = calc_background_marginal_tpm(&candidate, current_state, &conditions, &tpm);
+    assert_almost_equal_matrix(&actual, &on_tpm);
+    notify_pass(1);
+
+    // CASE 2, C is noised
+    let conditions = [BackgroundCondition::Current, BackgroundCondition::Current, BackgroundCondition::Marginalized];
+    let actual = calc_background_marginal_tpm(&candidate, current_state, &conditions, &tpm);
+    assert_almost_equal_matrix(&actual, &((off_tpm + on_tpm) * 0.5));
+    notify_pass(2);
+}
+
+#[test]
+fn test_system_partition_scheme() {
+    let current_state = generate_reference_state();
+    let tpm = generate_reference_tpm();
+
+    // CASE 0, number of cuts
+    assert_eq!(generate_system_cuts(SystemPartitionScheme::Unidirectional, 3).count(), 6);
+    assert_eq!(generate_system_cuts(SystemPartitionScheme::Bidirectional, 3).count(), 3);
+    assert_eq!(generate_directed_partitions(2).len(), 3);
+    notify_pass(0);
+
+    // CASE 1, severed connections
+    let partition = SystemPartition { cut_from: vec![0], cut_to: vec![1, 2] };
+    assert_eq!(SystemCut::Unidirectional(partition.clone()).severed_inputs(3), [0b000, 0b001, 0b001]);
+    assert_eq!(SystemCut::Bidirectional(partition.clone()).severed_inputs(3), [0b110, 0b001, 0b001]);
+    let directed = SystemCut::Directed(DirectedPartition {
+        parts: vec![vec![0], vec![1, 2]],
+        directions: vec![CutDirection::Output, CutDirection::Input],
+    });
+    assert_eq!(directed.severed_inputs(3), [0b000, 0b001, 0b001]);
+    assert_almost_equal_matrix(&calc_cut_marginal_tpm(&directed, &tpm), &calc_partitioned_marginal_tpm(&partition, &tpm));
+    notify_pass(1);
+
+    // CASE 2, bidirectional cut noises both sides
+    let actual = calc_cut_marginal_tpm(&SystemCut::Bidirectional(partition), &tpm);
+    let unidirectional = calc_partitioned_marginal_tpm(&SystemPartition { cut_from: vec![1, 2], cut_to: vec![0] }, &tpm);
+    let reversed = calc_partitioned_marginal_tpm(&SystemPartition { cut_from: vec![0], cut_to: vec![1, 2] }, &tpm);
+    let expected = calc_cut_marginal_tpm(&SystemCut::Directed(DirectedPartition {
+        parts: vec![vec![0], vec![1, 2]],
+        directions: vec![CutDirection::Bidirectional, CutDirection::Bidirectional],
+    }), &tpm);
+    assert_almost_equal_matrix(&actual, &expected);
+    assert!(actual != unidirectional && actual != reversed);
+    notify_pass(2);
+
+    // CASE 3, MIP of each scheme
+    let tpm = Arc::new(tpm);
+    let config = AnalysisConfig::builder().system_scheme(SystemPartitionScheme::Bidirectional).num_threads(2).build().unwrap();
+    let bidirectional = search_constellation_with_mip(current_state, &tpm, &config).mip;
+    assert!(matches!(bidirectional.partition, SystemCut::Bidirectional(_)));
+    let config = AnalysisConfig::builder().system_scheme(SystemPartitionScheme::Directed).num_threads(2).build().unwrap();
+    let directed = search_constellation_with_mip(current_state, &tpm, &config).mip;
+    assert!(matches!(directed.partition, SystemCut::Directed(_)));
+    assert!(directed.phi <= 1.9166666666 + 1.0e-7); // unidirectional cuts are included
+    notify_pass(3);
+}
+
+#[test]
+fn test_mechanism_partition_scheme() {
+    let tpm = generate_reference_tpm();
+    let current_state = generate_reference_state();
+
+    let cause_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &tpm);
+    let effect_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &tpm);
+
+    // CASE 0, number of partitions
+    assert_eq!(generate_mechanism_partitions(MechanismPartitionScheme::Bipartition, 1, 2).count(), 3);
+    assert_eq!(generate_mechanism_partitions(MechanismPartitionScheme::All, 1, 2).count(), 4);
+    assert_eq!(generate_mechanism_partitions(MechanismPartitionScheme::All, 2, 1).count(), 1);
+    assert_eq!(generate_mechanism_partitions(MechanismPartitionScheme::Wedge, 1, 2).count(), 3);
+    assert_eq!(generate_mechanism_partitions(MechanismPartitionScheme::Wedge, 3, 1).count(), 1);
+    notify_pass(0);
+
+    // CASE 1, wedge leaves a purview part without mechanism, but not mechanism parts without purview
+    let expected = MechanismPartition {
+        parts: vec![
+            MechanismPart { purview: vec![0], mechanism: vec![0] },
+            MechanismPart { purview: vec![1], mechanism: vec![1] },
+            MechanismPart { purview: vec![2], mechanism: vec![] },
+        ],
+    };
+    let compressible = MechanismPartition {
+        parts: vec![
+            MechanismPart { purview: vec![], mechanism: vec![0] },
+            MechanismPart { purview: vec![], mechanism: vec![1] },
+            MechanismPart { purview: vec![0], mechanism: vec![] },
+        ],
+    };
+    assert!(generate_mechanism_partitions(MechanismPartitionScheme::Wedge, 3, 2).any(|p| p.is_equivalent(&expected)));
+    assert!(!generate_mechanism_partitions(MechanismPartitionScheme::Wedge, 1, 2).any(|p| p.is_equivalent(&compressible)));
+    notify_pass(1);
+
+    // CASE 2, partitions into any number of parts do not raise phi of ABC
+    let mechanism = BitBasis::construct_from_mask(0b111, 3);
+    let bipartition = search_core_with_parts(&mechanism, &cause_parts, &AnalysisConfig::default());
+    let config = AnalysisConfig { mechanism_scheme: MechanismPartitionScheme::All, ..AnalysisConfig::default() };
+    let all = search_core_with_parts(&mechanism, &cause_parts, &config);
+    assert!(all.phi <= bipartition.phi + 1.0e-7);
+    notify_pass(2);
+
+    // CASE 3, single element is cut only from its whole purview, A/[] x []/ABC, as PyPhi
+    let mechanism = BitBasis::construct_from_mask(0b001, 3);
+    let config = AnalysisConfig { mechanism_scheme: MechanismPartitionScheme::Wedge, ..AnalysisConfig::default() };
+    let concept = search_concept_with_parts(&mechanism, &cause_parts, &effect_parts, &config);
+    let expected = MechanismPartition {
+        parts: vec![
+            MechanismPart { purview: vec![], mechanism: vec![0] },
+            MechanismPart { purview: vec![0, 1, 2], mechanism: vec![] },
+        ],
+    };
+    assert_almost_equal_scalar(concept.core_cause.phi, 0.3333333333);
+    assert_almost_equal_scalar(concept.core_effect.phi, 0.25);
+    assert_almost_equal_scalar(concept.phi, 0.25);
+    assert!(concept.core_cause.partition.is_equivalent(&expected));
+    notify_pass(3);
+}
+
+#[test]
+fn test_report_ties() {
+    let tpm = generate_reference_tpm();
+    let current_state = generate_reference_state();
+
+    let cause_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &tpm);
+
+    // CASE 0, Fig.10 B has tied cause purviews A, C and AC
+    let mechanism = BitBasis::construct_from_mask(0b010, 3);
+    let config = AnalysisConfig { report_ties: true, ..AnalysisConfig::default() };
+    let core = search_core_with_parts(&mechanism, &cause_parts, &config);
+    let tied: Vec<usize> = core.tied_purviews.iter().map(|purview| purview.to_mask()).collect();
+    assert_almost_equal_scalar(core.phi, 0.1666666666);
+    assert_eq!(tied, [0b001, 0b100, 0b101]);
+    assert!(tied.contains(&core.purview.to_mask()));
+    assert!(core.tied_partitions.contains(&core.partition));
+    notify_pass(0);
+
+    // CASE 1, ties are not reported by default
+    let core = search_core_with_parts(&mechanism, &cause_parts, &AnalysisConfig::default());
+    assert!(core.tied_purviews.is_empty());
+    assert!(core.tied_partitions.is_empty());
+    notify_pass(1);
+
+    // CASE 2, two elements copying each other can be cut in either direction
+    let tpm = Arc::new(generate_swap_tpm());
+
+    (1..=4).for_each(|num_threads| {
+        let config = AnalysisConfig::builder().report_ties(true).num_threads(num_threads).build().unwrap();
+        let mip = search_constellation_with_mip(0b01, &tpm, &config).mip;
+
+        assert_eq!(mip.tied_partitions.len(), 2);
+        assert!(mip.tied_partitions.contains(&SystemCut::Unidirectional(SystemPartition { cut_from: vec![0], cut_to: vec![1] })));
+        assert!(mip.tied_partitions.contains(&SystemCut::Unidirectional(SystemPartition { cut_from: vec![1], cut_to: vec![0] })));
+        assert!(mip.tied_partitions.contains(&mip.partition));
+    });
+    notify_pass(2);
+}
+
+#[test]
+fn test_deterministic_mip() {
+    let swap_tpm = Arc::new(generate_swap_tpm());
+
+    // CASE 0, the first one of tied cuts is selected
+    let expected = SystemCut::Unidirectional(SystemPartition { cut_from: vec![1], cut_to: vec![0] });
+    (1..=4).for_each(|num_threads| {
+        let config = AnalysisConfig::builder().num_threads(num_threads).build().unwrap();
+        let mip = search_constellation_with_mip(0b01, &swap_tpm, &config).mip;
+        assert_eq!(mip.partition, expected);
+    });
+    notify_pass(0);
+
+    // CASE 1, tied cuts are ordered as enumerated
+    let config = AnalysisConfig::builder().report_ties(true).num_threads(4).build().unwrap();
+    let mip = search_constellation_with_mip(0b01, &swap_tpm, &config).mip;
+    assert_eq!(mip.tied_partitions[0], expected);
+    notify_pass(1);
+
+    // CASE 2, the same MIP regardless of the number of threads
+    let tpm = Arc::new(generate_reference_tpm());
+    let current_state = generate_reference_state();
+    let config = AnalysisConfig::builder().system_scheme(SystemPartitionScheme::Directed).build().unwrap();
+
+    let single = search_constellation_with_mip(current_state, &tpm, &config).mip;
+    (2..=4).for_each(|num_threads| {
+        let config = AnalysisConfig { num_threads, ..config.clone() };
+        let multi = search_constellation_with_mip(current_state, &tpm, &config).mip;
+        assert_eq!(multi.partition, single.partition);
+        assert_eq!(multi.phi, single.phi);
+    });
+    notify_pass(2);
+}
+
+#[test]
+fn test_tolerance() {
+    let tpm = generate_reference_tpm();
+    let current_state = generate_reference_state();
+
+    let cause_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, current_state, &tpm);
+    let effect_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::EFFECT, current_state, &tpm);
+
+    // CASE 0, default tolerance is the same as before
+    assert!(matches!(compare_roughly(1.0, 1.0 + 0.5 * DEFAULT_TOLERANCE), Comparison::AlmostEqual));
+    assert!(matches!(compare_within(1.0, 1.01, 0.1), Comparison::AlmostEqual));
+    assert!(matches!(compare_within(1.0, 1.01, 0.001), Comparison::NotEqual(_)));
+    notify_pass(0);
+
+    // CASE 1, concepts of small phi are reducible under a loose tolerance
+    let config = AnalysisConfig { tolerance: 0.2, ..AnalysisConfig::default() };
+    let mechanism = BitBasis::construct_from_mask(0b001, 3);
+    let concept = search_concept_with_parts(&mechanism, &cause_parts, &effect_parts, &config);
+    assert_almost_equal_scalar(concept.phi, 0.0);
+
+    let default_constellation = search_constellation_with_parts(&cause_parts, &effect_parts, &AnalysisConfig::default());
+    let loose_constellation = search_constellation_with_parts(&cause_parts, &effect_parts, &config);
+    assert_eq!(default_constellation.concepts.len(), 6);
+    assert_eq!(loose_constellation.concepts.len(), 4); // A and B have phi of 1/6
+    notify_pass(1);
+}
+
+#[test]
+fn test_analysis_config() {
+    let current_state = generate_reference_state();
+    let tpm = generate_reference_tpm();
+
+    // CASE 0, builder starts from IIT 3.0 settings
+    assert_eq!(AnalysisConfig::builder().build().unwrap(), AnalysisConfig::default());
+
+    let config = AnalysisConfig::builder()
+        .system_scheme(SystemPartitionScheme::Bidirectional)
+        .mechanism_scheme(MechanismPartitionScheme::Wedge)
+        .report_ties(true)
+        .tolerance(1.0e-5)
+        .distance(DistanceMeasure::L1)
+        .background(&[BackgroundCondition::Marginalized])
+        .num_threads(3)
+        .log(true)
+        .build()
+        .unwrap();
+    assert_eq!(config.system_scheme, SystemPartitionScheme::Bidirectional);
+    assert_eq!(config.mechanism_scheme, MechanismPartitionScheme::Wedge);
+    assert!(config.report_ties && config.log);
+    assert_eq!(config.tolerance, 1.0e-5);
+    assert_eq!(config.distance, DistanceMeasure::L1);
+    assert_eq!(config.background, [BackgroundCondition::Marginalized]);
+    assert_eq!(config.num_threads, 3);
+    notify_pass(0);
+
+    // CASE 1, invalid settings
+    assert!(matches!(AnalysisConfig::builder().num_threads(0).build(), Err(PhiError::InvalidConfig(_))));
+    assert!(matches!(AnalysisConfig::builder().tolerance(0.0).build(), Err(PhiError::InvalidConfig(_))));
+    assert!(matches!(AnalysisConfig::builder().tolerance(f64::NAN).build(), Err(PhiError::InvalidConfig(_))));
+    let unbuilt = AnalysisConfig { num_threads: 0, ..AnalysisConfig::default() };
+    assert!(matches!(try_search_constellation_with_mip(current_state, &Arc::new(tpm.clone()), &unbuilt, &CancellationToken::new()), Err(PhiError::InvalidConfig(_))));
+    assert!(matches!(try_search_complex(current_state, &tpm, &unbuilt, &SilentObserver, &CancellationToken::new()), Err(PhiError::InvalidConfig(_))));
+    notify_pass(1);
+
+    // CASE 2, L1 distance
+    let from = na::DVector::<f64>::from_vec(vec![1.0, 0.0, 0.0, 0.0]);
+    let to = na::DVector::<f64>::from_vec(vec![0.0, 0.0, 0.0, 1.0]);
+    assert_almost_equal_scalar(calc_repertoire_distance(&from, &to, DistanceMeasure::Emd), 2.0);
+    assert_almost_equal_scalar(calc_repertoire_distance(&from, &to, DistanceMeasure::L1), 2.0);
+    let to = na::DVector::<f64>::from_vec(vec![0.0, 1.0, 0.0, 0.0]);
+    assert_almost_equal_scalar(calc_repertoire_distance(&from, &to, DistanceMeasure::Emd), 1.0);
+    assert_almost_equal_scalar(calc_repertoire_distance(&from, &to, DistanceMeasure::L1), 2.0);
+    notify_pass(2);
+
+    // CASE 3, background conditions of the complex search
+    let config = AnalysisConfig::builder().num_threads(2).build().unwrap();
+    let current = search_complex(current_state, &tpm, &config);
+    let config = AnalysisConfig::builder().background(&[BackgroundCondition::Current; 3]).num_threads(2).build().unwrap();
+    let explicit = search_complex(current_state, &tpm, &config);
+    assert_eq!(current.elements, explicit.elements);
+    assert_almost_equal_scalar(current.constellation.mip.phi, explicit.constellation.mip.phi);
+    notify_pass(3);
+}
+
+#[test]
+fn test_network() {
+    let config = AnalysisConfig::default();
+
+    // CASE 0, names follow the order of SIF
+    let network = Network::from_link_infos(parse_sif_str("Left COPY Right\nRight NOT Left\n").unwrap(), 1).unwrap();
+    assert_eq!(network.names, vec!["Left", "Right"]);
+    assert_eq!(network.size(), 2);
+    assert_eq!(network.element_index("Right").unwrap(), 1);
+    assert!(matches!(network.element_index("Center"), Err(PhiError::UnknownElement(_))));
+    assert_eq!(format_elements(&[0, 1], &network.names), "Left,Right");
+    notify_pass(0);
+
+    // CASE 1, default names and validation
+    let network = Network::from_tpm(generate_reference_tpm()).unwrap();
+    assert_eq!(network.names, vec!["A", "B", "C"]);
+    assert!(matches!(Network::construct(vec!["A".to_string()], generate_reference_tpm()), Err(PhiError::InvalidTpm(_))));
+    let names = vec!["A".to_string(), "B".to_string(), "A".to_string()];
+    assert!(matches!(Network::construct(names, generate_reference_tpm()), Err(PhiError::DuplicateElement(_))));
+    notify_pass(1);
+
+    // CASE 2, cuts and purviews
+    let cut = SystemCut::Unidirectional(SystemPartition { cut_from: vec![0, 1], cut_to: vec![2] });
+    assert_eq!(network.named(&cut).to_string(), "AB =/=> C");
+    let cut = SystemCut::Directed(DirectedPartition { parts: vec![vec![0], vec![1, 2]], directions: vec![CutDirection::Input, CutDirection::Output] });
+    assert_eq!(network.named(&cut).to_string(), "=/=> A, <=/= BC");
+    assert_eq!(network.named(BitBasis::construct_from_mask(0b101, 3)).to_string(), "AC");
+    assert_eq!(network.named(BitBasis::null_basis(3)).to_string(), "[]");
+    notify_pass(2);
+
+    // CASE 3, complexes are displayed with names of their elements
+    let tpm = generate_reference_tpm();
+    let complex = search_complex(generate_reference_state(), &tpm, &config);
+    let text = network.named(&complex).to_string();
+    assert!(text.starts_with("Complex: ABC\nMIP: AB =/=> C"));
+    assert!(text.contains("\nAB: phi: 0.25, cause: ABC"));
+    assert!(!text.contains("CAUSE:"));
+    assert!(format!("{:#}", network.named(&complex)).contains("\n    CAUSE:\n        ABC  P\n        000  "));
+    notify_pass(3);
+}
+
+#[test]
+fn test_state_format() {
+    let network = Network::from_tpm(generate_reference_tpm()).unwrap();
+
+    // CASE 0, both notations are in the order of elements
+    assert_eq!(network.parse_state("A=1 B=0 C=0").unwrap(), 0b001);
+    assert_eq!(network.parse_state("C=1,A=1, B=0").unwrap(), 0b101);
+    assert_eq!(network.parse_state("100").unwrap(), 0b001);
+    assert_eq!(network.parse_state(" 011 ").unwrap(), 0b110);
+    assert_eq!(network.format_state(0b110), "A=0 B=1 C=1");
+    assert_eq!(format_state_bits(0b110, 3), "011");
+    (0..8).for_each(|state| {
+        assert_eq!(network.parse_state(&network.format_state(state)).unwrap(), state);
+        assert_eq!(network.parse_state(&format_state_bits(state, 3)).unwrap(), state);
+    });
+    notify_pass(0);
+
+    // CASE 1, broken states
+    assert!(matches!(network.parse_state("10"), Err(PhiError::InvalidState(_))));
+    assert!(matches!(network.parse_state("A=1 B=0"), Err(PhiError::InvalidState(_))));
+    assert!(matches!(network.parse_state("A=1 B=0 C=2"), Err(PhiError::InvalidState(_))));
+    assert!(matches!(network.parse_state("A=1 A=0 B=0 C=0"), Err(PhiError::InvalidState(_))));
+    assert!(matches!(network.parse_state("A=1 B=0 D=0"), Err(PhiError::UnknownElement(_))));
+    assert!(matches!(network.parse_state("A B C"), Err(PhiError::InvalidState(_))));
+    notify_pass(1);
+
+    // CASE 2, repertoires are labelled by states
+    let repertoire = na::DVector::<f64>::from_vec(vec![0.5, 0.25, 0.25, 0.0]);
+    let names = vec!["Left".to_string(), "Right".to_string()];
+    assert_eq!(format_repertoire(&repertoire, &names), "Left,Right  P\n00          0.5000\n10          0.2500\n01          0.2500\n11          0.0000");
+    notify_pass(2);
+
+    // CASE 3, debug output of core repertoires
+    let config = AnalysisConfig::default();
+    let cause_parts = generate_all_repertoire_parts(crate::mechanism::RepertoireType::CAUSE, generate_reference_state(), &generate_reference_tpm());
+    let core = search_core_with_parts(&BitBasis::construct_from_mask(0b011, 3), &cause_parts, &config);
+    assert!(format!("{:?}", core).contains(&format_repertoire(&core.repertoire, &network.names)));
+    notify_pass(3);
+}
+
+type ProgressRecord = (Vec<usize>, f64, usize, usize);
+
+struct RecordingObserver {
+    records: Mutex<Vec<ProgressRecord>>,
+    cancel_after: Option<(usize, CancellationToken)>,
+}
+
+impl ProgressObserver for RecordingObserver {
+    fn notify(&self, progress: &Progress) {
+        let mut records = self.records.lock().unwrap();
+        records.push((progress.candidate.to_vec(), progress.phi, progress.current_count, progress.total_count));
+        assert!(progress.elapsed <= progress.total_elapsed);
+
+        if let Some((count, token)) = &self.cancel_after {
+            if records.len() == *count {
+                token.cancel();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_progress() {
+    let current_state = generate_reference_state();
+    let tpm = generate_reference_tpm();
+    let config = AnalysisConfig::builder().num_threads(2).build().unwrap();
+
+    // CASE 0, every candidate is notified in order
+    let observer = RecordingObserver { records: Mutex::new(Vec::new()), cancel_after: None };
+    let complex = try_search_complex(current_state, &tpm, &config, &observer, &CancellationToken::new()).unwrap();
+    let records = observer.records.into_inner().unwrap();
+    assert_eq!(records.len(), 7);
+    assert_eq!(records[0].0, vec![0]);
+    assert_eq!(records[6].0, vec![0, 1, 2]);
+    assert_almost_equal_scalar(records[6].1, complex.constellation.mip.phi);
+    records.iter().enumerate().for_each(|(i, record)| {
+        assert_eq!(record.2, i + 1);
+        assert_eq!(record.3, 7);
+    });
+    notify_pass(0);
+
+    // CASE 1, cancelled between candidates
+    let token = CancellationToken::new();
+    let observer = RecordingObserver { records: Mutex::new(Vec::new()), cancel_after: Some((3, token.clone())) };
+    assert!(matches!(try_search_complex(current_state, &tpm, &config, &observer, &token), Err(PhiError::Cancelled)));
+    assert_eq!(observer.records.into_inner().unwrap().len(), 3);
+    notify_pass(1);
+
+    // CASE 2, cancelled before system partitions
+    let token = CancellationToken::new();
+    token.cancel();
+    assert!(matches!(try_search_constellation_with_mip(current_state, &Arc::new(tpm.clone()), &config, &token), Err(PhiError::Cancelled)));
+    assert!(matches!(try_search_complex(current_state, &tpm, &config, &SilentObserver, &token), Err(PhiError::Cancelled)));
+    notify_pass(2);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serialize() {
+    use crate::{network::Named, serialize::{from_binary, from_json, to_binary, to_json}, system::{Complex, MinimumInformationPartition}};
+
+    let current_state = generate_reference_state();
+    let tpm = generate_reference_tpm();
+    let config = AnalysisConfig::builder().num_threads(2).report_ties(true).build().unwrap();
+    let complex = search_complex(current_state, &tpm, &config);
+
+    // CASE 0, JSON
+    let json = to_json(&complex).unwrap();
+    let restored: Complex = from_json(&json).unwrap();
+    assert_eq!(restored.elements, complex.elements);
+    assert_eq!(restored.constellation.mip.partition, complex.constellation.mip.partition);
+    assert_eq!(restored.constellation.mip.tied_partitions, complex.constellation.mip.tied_partitions);
+    assert_almost_equal_scalar(restored.constellation.mip.phi, complex.constellation.mip.phi);
+    assert_almost_equal_matrix(&restored.marginal_tpm, &complex.marginal_tpm);
+    assert_eq!(restored.constellation.concepts.len(), complex.constellation.concepts.len());
+    restored.constellation.concepts.iter().zip(complex.constellation.concepts.iter()).for_each(|(r, c)| {
+        assert_eq!(r.mechanism.to_mask(), c.mechanism.to_mask());
+        assert_eq!(r.core_cause.purview.to_mask(), c.core_cause.purview.to_mask());
+        assert_eq!(r.core_effect.partition, c.core_effect.partition);
+        assert_almost_equal_vec(&r.core_cause.repertoire, &c.core_cause.repertoire);
+        assert_almost_equal_scalar(r.phi, c.phi);
+    });
+    notify_pass(0);
+
+    // CASE 1, binary is exact
+    let binary = to_binary(&complex).unwrap();
+    let restored: Complex = from_binary(&binary).unwrap();
+    assert_eq!(to_binary(&restored).unwrap(), binary);
+    assert!(binary.len() < json.len());
+    notify_pass(1);
+
+    // CASE 2, configuration and broken input
+    let restored: AnalysisConfig = from_json(&to_json(&config).unwrap()).unwrap();
+    assert_eq!(restored, config);
+    assert!(matches!(from_json::<Complex>("{"), Err(PhiError::Serialization(_))));
+    notify_pass(2);
+
+    // CASE 3, names are kept with results
+    let network = Network::from_tpm(tpm).unwrap();
+    let json = to_json(&network.named(&complex.constellation.mip)).unwrap();
+    let restored: Named<MinimumInformationPartition> = from_json(&json).unwrap();
+    assert_eq!(restored.names, network.names);
+    assert_eq!(Named { names: restored.names.clone(), value: &restored.value.partition }.to_string(), "AB =/=> C");
+    assert_almost_equal_scalar(restored.value.phi, complex.constellation.mip.phi);
+    notify_pass(3);
+}
+
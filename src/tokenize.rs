@@ -0,0 +1,30 @@
+const COMMENT_MARKER: char = '#';
+
+
+// return tokens with their 1-based column, ignoring a comment from `#` to the end of the line
+pub fn tokenize_line(line: &str, is_separator: fn(char) -> bool) -> Vec<(usize, &str)> {
+    let content = match line.find(COMMENT_MARKER) {
+        Some(end) => &line[..end],
+        None => line,
+    };
+
+    let mut tokens = Vec::<(usize, &str)>::new();
+    let mut start: Option<(usize, usize)> = None;
+
+    for (column, (offset, c)) in content.char_indices().enumerate() {
+        match (is_separator(c), start) {
+            (true, Some((token_column, token_offset))) => {
+                tokens.push((token_column + 1, &content[token_offset..offset]));
+                start = None;
+            },
+            (false, None) => start = Some((column, offset)),
+            _ => (),
+        }
+    }
+
+    if let Some((token_column, token_offset)) = start {
+        tokens.push((token_column + 1, &content[token_offset..]));
+    }
+
+    tokens
+}
@@ -1,8 +1,139 @@
-use std::{sync::{Arc, Mutex}, thread::{self, JoinHandle}, usize};
+use std::{sync::{Arc, Mutex}, thread::{self, JoinHandle}};
 use nalgebra as na;
-use crate::{basis::BitBasis, link_fn::LinkFn, partition::SystemPartition};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::{basis::BitBasis, bitwise::{BitSet, USIZE_BITS, generate_indices}, compare::{Comparison, compare_roughly}, error::{PhiError, Result}, link_fn::BoxedLink, partition::{SystemCut, SystemPartition}};
 
 
+pub trait TransitionModel {
+    // return the number of states, which is 2^n for n elements
+    fn state_size(&self) -> usize;
+
+    fn transition(&self, from: usize, to: usize) -> f64;
+}
+
+impl TransitionModel for na::DMatrix<f64> {
+    fn state_size(&self) -> usize {
+        self.nrows()
+    }
+
+    fn transition(&self, from: usize, to: usize) -> f64 {
+        self[(from, to)]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StateByNodeTpm {
+    pub probs: na::DMatrix<f64>, // 2^n x n, probability that each element is ON after each state
+}
+
+impl StateByNodeTpm {
+    pub fn construct(probs: na::DMatrix<f64>) -> Result<StateByNodeTpm> {
+        let element_size = probs.ncols();
+        if element_size == 0 || element_size >= USIZE_BITS || probs.nrows() != 1 << element_size {
+            return Err(PhiError::InvalidTpm(format!("{}x{} is not a state-by-node shape", probs.nrows(), element_size)));
+        }
+
+        Ok(StateByNodeTpm { probs })
+    }
+
+    // lossless if the elements are conditionally independent
+    pub fn from_state_by_state(tpm: &na::DMatrix<f64>) -> Result<StateByNodeTpm> {
+        if !tpm.nrows().is_power_of_two() || tpm.nrows() != tpm.ncols() {
+            return Err(PhiError::InvalidTpm(format!("{}x{} is not a state-by-state shape", tpm.nrows(), tpm.ncols())));
+        }
+
+        let element_size = tpm.nrows().trailing_zeros() as usize;
+        let mut probs = na::DMatrix::<f64>::zeros(tpm.nrows(), element_size);
+
+        tpm.row_iter().zip(probs.row_iter_mut()).for_each(|(tpm_row, mut row)| {
+            tpm_row.iter().enumerate().for_each(|(state, &p)| {
+                (0..element_size).filter(|&i| state & (1 << i) != 0).for_each(|i| row[i] += p);
+            });
+        });
+
+        StateByNodeTpm::construct(probs)
+    }
+
+    pub fn to_state_by_state(&self) -> na::DMatrix<f64> {
+        expand_state_by_node(&self.probs)
+    }
+
+    pub fn element_size(&self) -> usize {
+        self.probs.ncols()
+    }
+}
+
+impl TransitionModel for StateByNodeTpm {
+    fn state_size(&self) -> usize {
+        self.probs.nrows()
+    }
+
+    fn transition(&self, from: usize, to: usize) -> f64 {
+        self.probs.row(from).iter().enumerate().fold(1.0, |acc, (i, &p)| {
+            if to & (1 << i) == 0 {
+                acc * (1.0 - p)
+            } else {
+                acc * p
+            }
+        })
+    }
+}
+
+// each element reads only its inputs, so that networks too large for a TPM can be handled element-wise
+pub struct LocalLink {
+    pub inputs: Vec<usize>, // distinct elements, the i-th one is given to `link` as the i-th bit
+    pub link: BoxedLink,
+}
+
+impl LocalLink {
+    fn probability(&self, env: usize) -> f64 {
+        self.link.probability(env, !(usize::MAX << self.inputs.len()))
+    }
+
+    // averaged uniformly over the inputs out of `fixed_mask`
+    fn calc_averaged_probability<S: BitSet>(&self, state: &S, fixed_mask: &S) -> f64 {
+        let mut fixed_env = 0;
+        let mut free_vectors = Vec::<usize>::new();
+
+        self.inputs.iter().enumerate().for_each(|(i, &input)| {
+            if !fixed_mask.contains(input) {
+                free_vectors.push(1 << i);
+            } else if state.contains(input) {
+                fixed_env |= 1 << i;
+            }
+        });
+
+        let free_basis: BitBasis = BitBasis::construct_from_vectors(&free_vectors, self.inputs.len());
+        let sum: f64 = free_basis.span(fixed_env).map(|env| self.probability(env)).sum();
+
+        sum / free_basis.image_size() as f64
+    }
+}
+
+pub struct LinkModel {
+    pub links: Vec<LocalLink>, // indexed by element
+}
+
+impl LinkModel {
+    pub fn element_size(&self) -> usize {
+        self.links.len()
+    }
+
+    // probability that `element` is ON after `state`
+    pub fn probability<S: BitSet>(&self, element: usize, state: &S) -> f64 {
+        let link = &self.links[element];
+        let env = link.inputs.iter().enumerate().filter(|(_, &input)| state.contains(input)).fold(0, |acc, (i, _)| acc | (1 << i));
+
+        link.probability(env)
+    }
+
+    // same as above, but the inputs out of `fixed_mask` are noised uniformly
+    pub fn calc_averaged_probability<S: BitSet>(&self, element: usize, state: &S, fixed_mask: &S) -> f64 {
+        self.links[element].calc_averaged_probability(state, fixed_mask)
+    }
+}
+
 struct RowCounter {
     current: usize,
     size: usize,
@@ -33,9 +164,9 @@ fn update_row(nrow: usize, row: &na::DMatrix::<f64>, matrix: &Arc<Mutex<na::DMat
     target.copy_from(row);
 }
 
-fn predict_each_elements(env: usize, fns: &Vec<(LinkFn, usize)>, probs: &mut na::DMatrix<f64>) {
-    fns.iter().zip(probs.row_iter_mut()).for_each(|((link_fn, mask) ,mut row)| {
-        row[1] = link_fn(env, *mask);
+fn predict_each_elements(env: usize, fns: &[(BoxedLink, usize)], probs: &mut na::DMatrix<f64>) {
+    fns.iter().zip(probs.row_iter_mut()).for_each(|((link, mask) ,mut row)| {
+        row[1] = link.probability(env, *mask);
         row[0] = 1.0 - row[1]
     });
 }
@@ -57,8 +188,12 @@ fn calc_joint_prob(state: usize, probs: &na::DMatrix<f64>) -> f64 {
     prob
 }
 
-pub fn calc_tpm(fns: Vec<(LinkFn, usize)>, num_threads: usize) -> na::DMatrix<f64> {
+pub fn try_calc_tpm(fns: Vec<(BoxedLink, usize)>, num_threads: usize) -> Result<na::DMatrix<f64>> {
     let element_size: usize = fns.len();
+    if element_size == 0 || element_size >= USIZE_BITS {
+        return Err(PhiError::InvalidSystemSize(element_size));
+    }
+
     let matrix_size: usize = 1 << element_size;
 
     let counter = RowCounter { current: 0, size: matrix_size };
@@ -79,47 +214,217 @@ pub fn calc_tpm(fns: Vec<(LinkFn, usize)>, num_threads: usize) -> na::DMatrix<f6
             let mut row_buffer = na::DMatrix::<f64>::from_element(1, matrix_size, 0.0);
             let mut prob_buffer = na::DMatrix::<f64>::from_element(element_size, 2, 0.0);
 
-            loop {
-                if let Some(env) = get_assigned_nrow(&cloned_counter) {
-                    predict_each_elements(env, &cloned_fns, &mut prob_buffer);
-                    (0..matrix_size).for_each(|state| {
-                        row_buffer[state] = calc_joint_prob(state, &prob_buffer);
-                    });
-
-                    update_row(env, &row_buffer, &cloned_tpm);
-                } else {
-                    break;
-                }
+            while let Some(env) = get_assigned_nrow(&cloned_counter) {
+                predict_each_elements(env, &cloned_fns, &mut prob_buffer);
+                (0..matrix_size).for_each(|state| {
+                    row_buffer[state] = calc_joint_prob(state, &prob_buffer);
+                });
+
+                update_row(env, &row_buffer, &cloned_tpm);
             };
         });
 
         handles.push(handle);
     });
 
-    loop {
-        if let Some(handle) = handles.pop() {
-            handle.join().unwrap();
-        } else {
-            break;
-        }
+    while let Some(handle) = handles.pop() {
+        handle.join().map_err(|_| PhiError::WorkerPanicked)?;
     }
 
-    Arc::try_unwrap(shared_tpm).unwrap().into_inner().unwrap()
+    Arc::try_unwrap(shared_tpm).unwrap().into_inner().map_err(|_| PhiError::WorkerPanicked)
 }
 
-pub fn calc_fixed_marginal_tpm(surviving_basis: &BitBasis, state: usize, tpm: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+pub fn calc_tpm(fns: Vec<(BoxedLink, usize)>, num_threads: usize) -> na::DMatrix<f64> {
+    try_calc_tpm(fns, num_threads).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn calc_state_by_node_tpm(fns: Vec<(BoxedLink, usize)>) -> Result<StateByNodeTpm> {
+    let element_size: usize = fns.len();
+    if element_size == 0 || element_size >= USIZE_BITS {
+        return Err(PhiError::InvalidSystemSize(element_size));
+    }
+
+    let matrix_size: usize = 1 << element_size;
+    let mut probs = na::DMatrix::<f64>::zeros(matrix_size, element_size);
+    let mut prob_buffer = na::DMatrix::<f64>::zeros(element_size, 2);
+
+    (0..matrix_size).for_each(|env| {
+        predict_each_elements(env, &fns, &mut prob_buffer);
+        probs.row_mut(env).tr_copy_from(&prob_buffer.column(1));
+    });
+
+    Ok(StateByNodeTpm { probs })
+}
+
+// `state_by_node` must be 2^n x n, as checked by `StateByNodeTpm::construct`
+pub(crate) fn expand_state_by_node(state_by_node: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let element_size = state_by_node.ncols();
+    let matrix_size = state_by_node.nrows();
+    assert!(matrix_size == 1 << element_size);
+
+    let mut tpm = na::DMatrix::<f64>::zeros(matrix_size, matrix_size);
+    let mut prob_buffer = na::DMatrix::<f64>::zeros(element_size, 2);
+
+    (0..matrix_size).for_each(|env| {
+        state_by_node.row(env).iter().zip(prob_buffer.row_iter_mut()).for_each(|(&p, mut row)| {
+            row[1] = p;
+            row[0] = 1.0 - p;
+        });
+
+        (0..matrix_size).for_each(|state| {
+            tpm[(env, state)] = calc_joint_prob(state, &prob_buffer);
+        });
+    });
+
+    tpm
+}
+
+#[derive(Debug)]
+pub struct TpmReport {
+    pub valid_shape: bool, // square and power-of-two, other checks are skipped if false
+    pub unnormalized_rows: Vec<(usize, f64)>, // pairs of row and its sum
+    pub negative_entries: Vec<(usize, usize)>,
+    pub dependent_rows: Vec<usize>, // rows violating conditional independence across elements
+    pub reachable: bool, // whether the current state can be an effect of some state
+}
+
+impl TpmReport {
+    pub fn is_valid(&self) -> bool {
+        self.valid_shape && self.unnormalized_rows.is_empty() && self.negative_entries.is_empty()
+            && self.dependent_rows.is_empty() && self.reachable
+    }
+}
+
+fn is_almost_zero(x: f64) -> bool {
+    matches!(compare_roughly(x, 0.0), Comparison::AlmostEqual)
+}
+
+pub fn validate(tpm: &na::DMatrix<f64>, current_state: usize) -> TpmReport {
+    let nrows = tpm.nrows();
+
+    let mut report = TpmReport {
+        valid_shape: false,
+        unnormalized_rows: Vec::<(usize, f64)>::new(),
+        negative_entries: Vec::<(usize, usize)>::new(),
+        dependent_rows: Vec::<usize>::new(),
+        reachable: false,
+    };
+
+    let independent = match StateByNodeTpm::from_state_by_state(tpm) {
+        Ok(state_by_node) => state_by_node.to_state_by_state(),
+        Err(_) => return report,
+    };
+    report.valid_shape = true;
+
+    tpm.row_iter().zip(independent.row_iter()).enumerate().for_each(|(i, (row, independent_row))| {
+        let sum = row.sum();
+        if !is_almost_zero(sum - 1.0) {
+            report.unnormalized_rows.push((i, sum));
+        }
+
+        row.iter().enumerate().filter(|(_, &x)| x < 0.0).for_each(|(j, _)| {
+            report.negative_entries.push((i, j));
+        });
+
+        if row.iter().zip(independent_row.iter()).any(|(&x, &y)| !is_almost_zero(x - y)) {
+            report.dependent_rows.push(i);
+        }
+    });
+
+    report.reachable = current_state < nrows && tpm.column(current_state).iter().any(|&x| !is_almost_zero(x));
+
+    report
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BackgroundCondition {
+    Current, // fixed to the value in the current state
+    Fixed(bool),
+    Marginalized, // noised uniformly
+}
+
+// elements out of `conditions` are regarded as `BackgroundCondition::Current`
+pub fn calc_background_marginal_tpm(surviving_basis: &BitBasis, state: usize, conditions: &[BackgroundCondition], tpm: &na::DMatrix<f64>) -> na::DMatrix<f64> {
     let c_basis = surviving_basis.generate_complement_basis();
 
+    let mut fixed_state = 0;
+    let mut noised_vectors = Vec::<usize>::new();
+    c_basis.vectors.iter().for_each(|&vector| {
+        let condition = conditions.get(vector.trailing_zeros() as usize).copied().unwrap_or(BackgroundCondition::Current);
+
+        match condition {
+            BackgroundCondition::Current => fixed_state |= state & vector,
+            BackgroundCondition::Fixed(true) => fixed_state |= vector,
+            BackgroundCondition::Fixed(false) => (),
+            BackgroundCondition::Marginalized => noised_vectors.push(vector),
+        }
+    });
+
+    let noised_basis = BitBasis::construct_from_vectors(&noised_vectors, surviving_basis.max_dim);
+    let noise_weight = 1.0 / noised_basis.image_size() as f64;
+
     let maginal_dim = surviving_basis.image_size();
     let mut marginal = na::DMatrix::<f64>::zeros(maginal_dim, maginal_dim);
 
-    surviving_basis.span(c_basis.fixed_state(state)).enumerate().for_each(|(marginal_row, original_row)| {
-        surviving_basis.span(0).enumerate().for_each(|(marginal_col, eq_class)| {
-            let mut marginal_vec = marginal.row_mut(marginal_row);
+    surviving_basis.span(fixed_state).enumerate().for_each(|(marginal_row, fixed_row)| {
+        let mut marginal_vec = marginal.row_mut(marginal_row);
+
+        noised_basis.span(fixed_row).for_each(|original_row| {
             let original_vec = tpm.row(original_row);
 
-            marginal_vec[marginal_col] = c_basis.span(eq_class).fold(0.0, |acc, original_col| {
-                acc + original_vec[original_col]
+            surviving_basis.span(0).enumerate().for_each(|(marginal_col, eq_class)| {
+                marginal_vec[marginal_col] += noise_weight * c_basis.span(eq_class).fold(0.0, |acc, original_col| {
+                    acc + original_vec[original_col]
+                });
+            });
+        });
+    });
+
+    marginal
+}
+
+// same as `calc_background_marginal_tpm`, but computed from links without the TPM of the whole system
+pub fn calc_link_marginal_tpm<S: BitSet>(surviving_basis: &BitBasis<S>, state: &S, conditions: &[BackgroundCondition], model: &LinkModel) -> na::DMatrix<f64> {
+    // in the order of vectors, which is the order of bits in the marginal states
+    let surviving_mask = surviving_basis.to_mask();
+    let surviving_elements: Vec<usize> = surviving_basis.vectors.iter().flat_map(generate_indices).collect();
+
+    // only the inputs of surviving elements matter
+    let mut fixed_state = S::empty();
+    let mut noised_vectors = Vec::<S>::new();
+    let mut visited = S::empty();
+    surviving_elements.iter().flat_map(|&element| model.links[element].inputs.iter()).for_each(|&input| {
+        if surviving_mask.contains(input) || visited.contains(input) {
+            return;
+        }
+
+        visited.insert(input);
+
+        match conditions.get(input).copied().unwrap_or(BackgroundCondition::Current) {
+            BackgroundCondition::Current if state.contains(input) => fixed_state.insert(input),
+            BackgroundCondition::Fixed(true) => fixed_state.insert(input),
+            BackgroundCondition::Marginalized => noised_vectors.push(S::singleton(input)),
+            _ => (),
+        }
+    });
+
+    let noised_basis = BitBasis::construct_from_vectors(&noised_vectors, surviving_basis.max_dim);
+    let noise_weight = 1.0 / noised_basis.image_size() as f64;
+
+    let marginal_dim = surviving_basis.image_size();
+    let mut marginal = na::DMatrix::<f64>::zeros(marginal_dim, marginal_dim);
+    let mut prob_buffer = na::DMatrix::<f64>::zeros(surviving_elements.len(), 2);
+
+    surviving_basis.span(fixed_state).enumerate().for_each(|(marginal_row, fixed_row)| {
+        noised_basis.span(fixed_row).for_each(|original_row| {
+            surviving_elements.iter().zip(prob_buffer.row_iter_mut()).for_each(|(&element, mut row)| {
+                row[1] = model.probability(element, &original_row);
+                row[0] = 1.0 - row[1];
+            });
+
+            (0..marginal_dim).for_each(|marginal_col| {
+                marginal[(marginal_row, marginal_col)] += noise_weight * calc_joint_prob(marginal_col, &prob_buffer);
             });
         });
     });
@@ -127,6 +432,10 @@ pub fn calc_fixed_marginal_tpm(surviving_basis: &BitBasis, state: usize, tpm: &n
     marginal
 }
 
+pub fn calc_fixed_marginal_tpm(surviving_basis: &BitBasis, state: usize, tpm: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    calc_background_marginal_tpm(surviving_basis, state, &[], tpm)
+}
+
 pub fn calc_elementary_marginal_tpm(target_basis: &BitBasis, surviving_basis: &BitBasis, tpm: &na::DMatrix<f64>) -> na::DMatrix<f64> {
     let target_vector = target_basis.vectors[0];
     let c_target_basis = target_basis.generate_complement_basis();
@@ -177,19 +486,20 @@ pub fn calc_elementary_marginal_tpm(target_basis: &BitBasis, surviving_basis: &B
 }
 
 pub fn calc_partitioned_marginal_tpm(partition: &SystemPartition, tpm: &na::DMatrix<f64>) -> na::DMatrix<f64> {
-    let system_basis = BitBasis::construct_from_max_image_size(tpm.nrows());
-    let template_basis = system_basis.sub_basis(&[partition.cut_from[0]]);
-    let mut marginal = calc_elementary_marginal_tpm(&template_basis, &system_basis, tpm);
+    calc_cut_marginal_tpm(&SystemCut::Unidirectional(partition.clone()), tpm)
+}
 
-    (1..partition.cut_from.len()).for_each(|i| {
-        let intact_basis = system_basis.sub_basis(&[partition.cut_from[i]]);
-        marginal.component_mul_assign(&calc_elementary_marginal_tpm(&intact_basis, &system_basis, tpm));
-    });
+pub fn calc_cut_marginal_tpm(cut: &SystemCut, tpm: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let system_basis: BitBasis = BitBasis::construct_from_max_image_size(tpm.nrows());
+    let system_mask = system_basis.to_mask();
+    let severed = cut.severed_inputs(system_basis.max_dim);
+
+    let mut marginal = na::DMatrix::<f64>::from_element(tpm.nrows(), tpm.ncols(), 1.0);
 
-    let isolated_basis = system_basis.sub_basis(&partition.cut_to);
-    (0..partition.cut_to.len()).for_each(|i| {
-        let noised_basis = system_basis.sub_basis(&[partition.cut_to[i]]);
-        marginal.component_mul_assign(&calc_elementary_marginal_tpm(&noised_basis, &isolated_basis, tpm))
+    severed.iter().enumerate().for_each(|(i, &severed_mask)| {
+        let target_basis = system_basis.sub_basis(&[i]);
+        let surviving_basis = BitBasis::construct_from_mask(system_mask & !severed_mask, system_basis.max_dim);
+        marginal.component_mul_assign(&calc_elementary_marginal_tpm(&target_basis, &surviving_basis, tpm));
     });
 
     marginal
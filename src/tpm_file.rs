@@ -0,0 +1,92 @@
+use std::{fs::File, io::{BufRead, BufReader}, str::FromStr};
+use nalgebra as na;
+use crate::{error::{PhiError, Result}, tokenize::tokenize_line, tpm::expand_state_by_node};
+
+
+/*
+    States are ordered in little-endian as `BitBasis`: the i-th row (and column of state-by-state)
+    means the state whose j-th element is ON if and only if the j-th bit of i is 1.
+    e.g. for elements ABC, the rows are 000, A00, 0B0, AB0, 00C, ... in this order.
+
+    Values are separated by commas, semicolons or whitespaces, so CSV and plain-text work alike.
+    Blank lines and comments from '#' to the end of lines are ignored.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TpmFormat {
+    StateByState, // 2^n x 2^n, probability of the transition from row state to column state
+    StateByNode, // 2^n x n, probability that each element is ON after row state
+}
+
+fn tpm_error(line: usize, column: usize, message: String) -> PhiError {
+    PhiError::TpmFile { file: None, line, column, message }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == ';'
+}
+
+fn parse_tpm_line(line: &str, line_number: usize) -> Result<Vec<f64>> {
+    tokenize_line(line, is_separator).into_iter().map(|(column, token)| {
+        f64::from_str(token).map_err(|_| tpm_error(line_number, column, format!("Invalid number '{}'", token)))
+    }).collect()
+}
+
+fn check_shape(nrows: usize, ncols: usize, format: TpmFormat) -> Result<()> {
+    if nrows == 0 || nrows.count_ones() != 1 {
+        return Err(PhiError::InvalidTpm(format!("{} rows is not a power of two", nrows)));
+    }
+
+    let expected_ncols = match format {
+        TpmFormat::StateByState => nrows,
+        TpmFormat::StateByNode => nrows.trailing_zeros() as usize,
+    };
+
+    if ncols != expected_ncols {
+        return Err(PhiError::InvalidTpm(format!("{} rows need {} columns, but got {}", nrows, expected_ncols, ncols)));
+    }
+
+    Ok(())
+}
+
+// return a state-by-state TPM whichever format is given
+pub fn read_tpm_from<R: BufRead>(reader: R, format: TpmFormat) -> Result<na::DMatrix<f64>> {
+    let mut rows = Vec::<Vec<f64>>::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let values = parse_tpm_line(&line?, i + 1)?;
+
+        if values.is_empty() {
+            continue;
+        }
+
+        if let Some(first) = rows.first() {
+            if first.len() != values.len() {
+                return Err(tpm_error(i + 1, 1, format!("Expected {} values, but got {}", first.len(), values.len())));
+            }
+        }
+
+        rows.push(values);
+    }
+
+    let nrows = rows.len();
+    let ncols = rows.first().map_or(0, |row| row.len());
+    check_shape(nrows, ncols, format)?;
+
+    let matrix = na::DMatrix::<f64>::from_row_slice(nrows, ncols, &rows.concat());
+
+    Ok(match format {
+        TpmFormat::StateByState => matrix,
+        TpmFormat::StateByNode => expand_state_by_node(&matrix),
+    })
+}
+
+pub fn parse_tpm_str(tpm: &str, format: TpmFormat) -> Result<na::DMatrix<f64>> {
+    read_tpm_from(tpm.as_bytes(), format)
+}
+
+pub fn read_tpm(path: &str, format: TpmFormat) -> Result<na::DMatrix<f64>> {
+    let file = File::open(path)?;
+
+    read_tpm_from(BufReader::new(file), format).map_err(|e| e.with_file(path))
+}